@@ -1,13 +1,8 @@
-use async_openai::{
-    Client,
-    types::{
-        ChatCompletionRequestAssistantMessage,
-        ChatCompletionRequestAssistantMessageContent,
-        ChatCompletionRequestMessage, ChatCompletionRequestSystemMessage,
-        ChatCompletionRequestSystemMessageContent,
-        ChatCompletionRequestUserMessage,
-        ChatCompletionRequestUserMessageContent, CreateChatCompletionRequest,
-    },
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessage,
+    ChatCompletionRequestAssistantMessageContent, ChatCompletionRequestMessage,
+    ChatCompletionRequestUserMessage, ChatCompletionRequestUserMessageContent,
+    ResponseFormat,
 };
 use serde_json::{Map, Value};
 use serde_yaml;
@@ -15,33 +10,106 @@ use std::env;
 use std::fs;
 use std::path::Path;
 
+use crate::chat_backend::{ChatBackend, ChatRequest};
+use crate::instructions::Instructions;
+use crate::patch;
 use crate::response_formats::{
     ToolResponseFormat, parse_tool_response, response_format,
 };
+use crate::settings::InstructionsConfig;
+use crate::tools::{Tool, ToolConfig};
+use crate::yaml_utilities::{PromptFlags, system_prompt_sections};
 
-pub async fn choose_tool(
-    history: Vec<ChatCompletionRequestMessage>,
+// Tools whose `path` leads into a directory whose instruction files should
+// be loaded, if they were not yet.
+const PATH_TOOLS: [&str; 8] = [
+    "read_file",
+    "read_lines",
+    "write_file",
+    "write_lines",
+    "replace_in_file",
+    "list_files",
+    "search_files",
+    "find_files",
+];
+
+// The paths a call of `tool` works on: its `path`, or every file an
+// apply_patch call touches.
+fn touched_paths(tool: &str, args: &Value) -> Vec<String> {
+    if tool == "apply_patch" {
+        let patches = args["patch"]
+            .as_str()
+            .and_then(|text| patch::parse_patch(text).ok())
+            .unwrap_or_default();
+        return patches.iter().map(|p| p.path().to_string()).collect();
+    }
+    match args["path"].as_str() {
+        Some(path) if PATH_TOOLS.contains(&tool) => vec![path.to_string()],
+        _ => Vec::new(),
+    }
+}
+
+// Options of an agent run, the same for every request it makes.
+pub struct RunOptions {
+    pub model: String,
+    pub retries: u32,
+    pub max_tokens: u32,
+    pub stop: Vec<String>,
+    pub cache_prompt: bool,
+    pub max_tool_calls: u32,
+    pub verbose: bool,
+    pub tool_call_details: bool,
+    pub disable_agents_md: bool,
+    // Whether the project's own files may be used.
+    pub trusted: bool,
+    pub yolo: bool,
+    pub continue_task: bool,
+    pub plan_mode: bool,
+    pub no_shell: bool,
+    pub no_clarify: bool,
+    pub tool_response_format: ToolResponseFormat,
+    pub instructions: InstructionsConfig,
+}
+
+impl RunOptions {
+    // Instruction files belong to the project, like its config.
+    fn load_instructions(&self) -> bool {
+        !self.disable_agents_md && self.trusted
+    }
+
+    fn prompt_flags(&self) -> PromptFlags {
+        PromptFlags {
+            disable_agents_md: !self.load_instructions(),
+            plan_mode: self.plan_mode,
+            yolo: self.yolo,
+            no_clarify: self.no_clarify,
+            project_prompt: self.trusted,
+        }
+    }
+}
+
+// What every request carries besides the history.
+pub struct RequestPrompt {
+    pub tools: Vec<Tool>,
+    // The system prompt, split into its layout entries.
+    pub sections: Vec<(String, String)>,
+    pub response_format: Option<ResponseFormat>,
+}
+
+pub fn request_prompt(
     model: &str,
-    retries: u32,
-    max_tokens: u32,
-    base_url: &str,
-    verbose: bool,
-    yolo: bool,
-    disable_agents_md: bool,
-    plan_mode: bool,
+    flags: PromptFlags,
     no_shell: bool,
-    no_clarify: bool,
+    tool_config: &ToolConfig,
     tool_response_format: &ToolResponseFormat,
-) -> Result<Map<String, Value>, Box<dyn std::error::Error>> {
-    let api_key =
-        env::var("OPENROUTER_API_KEY").expect("OPENROUTER_API_KEY must be set");
-    let client = Client::with_config(
-        async_openai::config::OpenAIConfig::new()
-            .with_api_base(base_url)
-            .with_api_key(api_key),
+) -> RequestPrompt {
+    let tools = crate::tools::get_tools(
+        flags.yolo,
+        flags.plan_mode,
+        no_shell,
+        flags.no_clarify,
+        tool_config,
     );
-
-    let tools = crate::tools::get_tools(yolo, plan_mode, no_shell, no_clarify);
     let tool_names: Vec<serde_json::Value> = tools
         .iter()
         .map(|t| serde_json::Value::String(t.name().to_string()))
@@ -50,45 +118,58 @@ pub async fn choose_tool(
         tools.iter().map(|t| t.format()).collect::<Vec<_>>().join("\n");
     let current_dir = std::env::current_dir()
         .unwrap_or_else(|_| std::path::PathBuf::from("unknown"));
-    let system_content = crate::yaml_utilities::format_system_prompt(
+    let sections = system_prompt_sections(
         &current_dir,
-        disable_agents_md,
-        plan_mode,
+        flags,
         &available_tools_text,
-        yolo,
-        no_clarify,
         tool_response_format,
+        model,
+        &tool_config.shell.shell,
     );
-    let system_message = ChatCompletionRequestMessage::System(
-        ChatCompletionRequestSystemMessage {
-            content: ChatCompletionRequestSystemMessageContent::Text(
-                system_content,
-            ),
-            name: None,
-        },
-    );
-
-    let mut messages = vec![system_message];
-    messages.extend(history);
+    let tool_args_schemas: Vec<Value> =
+        tools.iter().map(|t| t.args_schema()).collect();
+    let response_format =
+        response_format(tool_response_format, &tool_names, &tool_args_schemas);
+    RequestPrompt {
+        tools,
+        sections,
+        response_format,
+    }
+}
 
-    let response_format_api =
-        response_format(tool_response_format, &tool_names);
+pub async fn choose_tool(
+    history: Vec<ChatCompletionRequestMessage>,
+    backend: &dyn ChatBackend,
+    tool_config: &ToolConfig,
+    options: &RunOptions,
+) -> Result<Map<String, Value>, Box<dyn std::error::Error>> {
+    let verbose = options.verbose;
+    let tool_response_format = &options.tool_response_format;
+    let prompt = request_prompt(
+        &options.model,
+        options.prompt_flags(),
+        options.no_shell,
+        tool_config,
+        tool_response_format,
+    );
 
-    let request = CreateChatCompletionRequest {
-        model: model.to_string(),
-        messages,
-        max_completion_tokens: Some(max_tokens),
-        response_format: response_format_api,
-        ..Default::default()
+    let request = ChatRequest {
+        model: options.model.clone(),
+        // Each section is a block of its own, so APIs with several system
+        // blocks see the prompt's structure.
+        system: prompt.sections.into_iter().map(|(_, text)| text).collect(),
+        messages: history,
+        max_tokens: options.max_tokens,
+        response_format: prompt.response_format,
+        stop: options.stop.clone(),
+        cache_prompt: options.cache_prompt,
     };
 
-    for attempt in 0..retries {
+    for attempt in 0..options.retries {
         if verbose {
-            println!("Sending openai request");
+            println!("Sending {} request", backend.name());
         }
-        let response = client.chat().create(request.clone()).await?;
-        let choice = response.choices.first().ok_or("No response")?;
-        let content = choice.message.content.as_ref().ok_or("No content")?;
+        let content = backend.complete(&request).await?;
         let trimmed = content.trim();
         if verbose {
             println!(
@@ -104,7 +185,7 @@ pub async fn choose_tool(
                 return Ok(normalized);
             }
             // Fallback: return a map for finish tool
-            let finish_tool_name = if plan_mode {
+            let finish_tool_name = if options.plan_mode {
                 "finish_planning"
             } else {
                 "finish_task"
@@ -125,47 +206,45 @@ pub async fn choose_tool(
 pub async fn execute_tool_call(
     tool_name: String,
     args: Value,
-    verbose: bool,
-    yolo: bool,
-    plan_mode: bool,
-    no_shell: bool,
-    no_clarify: bool,
+    tool_config: &ToolConfig,
+    options: &RunOptions,
 ) -> Result<String, Box<dyn std::error::Error>> {
-    let tools = crate::tools::get_tools(yolo, plan_mode, no_shell, no_clarify);
+    let tools = crate::tools::get_tools(
+        options.yolo,
+        options.plan_mode,
+        options.no_shell,
+        options.no_clarify,
+        tool_config,
+    );
     let tool = tools
         .into_iter()
         .find(|t| t.name() == tool_name)
         .ok_or_else(|| format!("Unknown tool: {}", tool_name))?;
-    tool.execute(args, verbose, yolo).await
+    let yolo = tool_config.yolo_for(&tool_name, options.yolo);
+    tool.execute(args, options.verbose, yolo).await
 }
 
 pub async fn loop_tools_until_finish(
     message: String,
-    model: &str,
-    retries: u32,
-    max_tokens: u32,
-    max_tool_calls: u32,
-    base_url: &str,
-    verbose: bool,
-    tool_call_details: bool,
-    disable_agents_md: bool,
-    yolo: bool,
-    continue_task: bool,
-    plan_mode: bool,
-    no_shell: bool,
-    no_clarify: bool,
-    tool_response_format: &ToolResponseFormat,
+    backend: &dyn ChatBackend,
+    tool_config: &ToolConfig,
+    options: &RunOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let verbose = options.verbose;
+    let mut instructions =
+        Instructions::new(&env::current_dir()?, &options.instructions);
     let home = env::var("HOME").expect("HOME not set");
     let history_dir = Path::new(&home).join(".local/share/attotool");
     fs::create_dir_all(&history_dir).ok();
     let history_path = history_dir.join("history.yaml");
     let mut history = Vec::new();
-    if continue_task {
+    if options.continue_task {
         let history_yaml = fs::read_to_string(&history_path)
             .expect("Failed to read history.yaml");
         history = serde_yaml::from_str(&history_yaml)
             .expect("Failed to parse history.yaml");
+        // The restored history already holds the instructions up to here.
+        instructions.initial();
         history.push(ChatCompletionRequestMessage::User(
             ChatCompletionRequestUserMessage {
                 content: ChatCompletionRequestUserMessageContent::Text(message),
@@ -173,19 +252,17 @@ pub async fn loop_tools_until_finish(
             },
         ));
     } else {
-        if !disable_agents_md && fs::metadata("AGENTS.md").is_ok() {
-            if let Ok(content) = fs::read_to_string("AGENTS.md") {
-                let formatted =
-                    format!("[read_file path: 'AGENTS.md']\n{}", content);
-                history.push(ChatCompletionRequestMessage::User(
-                    ChatCompletionRequestUserMessage {
-                        content: ChatCompletionRequestUserMessageContent::Text(
-                            formatted,
-                        ),
-                        name: None,
-                    },
-                ));
-            }
+        if options.load_instructions()
+            && let Some(formatted) = instructions.initial()
+        {
+            history.push(ChatCompletionRequestMessage::User(
+                ChatCompletionRequestUserMessage {
+                    content: ChatCompletionRequestUserMessageContent::Text(
+                        formatted,
+                    ),
+                    name: None,
+                },
+            ));
         }
         history.push(ChatCompletionRequestMessage::User(
             ChatCompletionRequestUserMessage {
@@ -196,21 +273,8 @@ pub async fn loop_tools_until_finish(
     }
     let mut tool_calls: Vec<(String, String)> = Vec::new();
     loop {
-        let mapping = choose_tool(
-            history.clone(),
-            model,
-            retries,
-            max_tokens,
-            base_url,
-            verbose,
-            yolo,
-            disable_agents_md,
-            plan_mode,
-            no_shell,
-            no_clarify,
-            tool_response_format,
-        )
-        .await?;
+        let mapping =
+            choose_tool(history.clone(), backend, tool_config, options).await?;
         let json_value = Value::Object(mapping.clone());
         let map = &mapping;
         let (key, value) =
@@ -221,11 +285,17 @@ pub async fn loop_tools_until_finish(
 
         let key = match tool.as_str() {
             "execute_shell_command" => "command",
+            "write_to_pty" => "input",
             "read_file" => "path",
             "write_file" => "path",
             "read_lines" => "path",
             "write_lines" => "path",
+            "replace_in_file" => "path",
             "list_files" => "path",
+            "search_files" => "pattern",
+            "find_files" => "pattern",
+            "http_request" => "url",
+            "web_search" => "query",
             "ask_for_clarification" => "",
             "describe_to_user" => "",
             "finish_task" => "",
@@ -233,11 +303,23 @@ pub async fn loop_tools_until_finish(
             _ => "",
         };
         let primary_value = if let Value::Object(ref m) = args_parsed {
-            m.get(key).and_then(|v| v.as_str()).unwrap_or("")
+            match m.get(key).and_then(|v| v.as_str()) {
+                Some(s) if !s.is_empty() => s.to_string(),
+                // execute_shell_command may be called with argv instead
+                _ => m
+                    .get("argv")
+                    .and_then(|v| v.as_array())
+                    .map(|argv| {
+                        argv.iter()
+                            .filter_map(|a| a.as_str())
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    })
+                    .unwrap_or_default(),
+            }
         } else {
-            ""
-        }
-        .to_string();
+            String::new()
+        };
 
         tool_calls.push((tool.clone(), primary_value.clone()));
         if verbose {
@@ -287,11 +369,8 @@ pub async fn loop_tools_until_finish(
         let result = match execute_tool_call(
             tool.clone(),
             args_parsed.clone(),
-            verbose,
-            yolo,
-            plan_mode,
-            no_shell,
-            no_clarify,
+            tool_config,
+            options,
         )
         .await
         {
@@ -299,7 +378,7 @@ pub async fn loop_tools_until_finish(
             Err(e) => {
                 let failure_message =
                     format!("[FAILURE {} {}]", tool, args_str);
-                if tool_call_details {
+                if options.tool_call_details {
                     println!("Tool call failed: {}", failure_message);
                     println!("Error: {}", e);
                 }
@@ -318,7 +397,7 @@ pub async fn loop_tools_until_finish(
 
         let prefixed_result =
             format!("[{} {}]\n{}", tool, primary_value, result);
-        if tool_call_details {
+        if options.tool_call_details {
             println!(
                 "Tool call result: {}",
                 prefixed_result.chars().take(500).collect::<String>()
@@ -333,11 +412,28 @@ pub async fn loop_tools_until_finish(
                 name: Some("tool_call_result".to_string()),
             },
         ));
+        let touched = if options.load_instructions() {
+            touched_paths(&tool, &args_parsed)
+        } else {
+            Vec::new()
+        };
+        for path in touched {
+            if let Some(formatted) = instructions.for_path(&path) {
+                history.push(ChatCompletionRequestMessage::User(
+                    ChatCompletionRequestUserMessage {
+                        content: ChatCompletionRequestUserMessageContent::Text(
+                            formatted,
+                        ),
+                        name: None,
+                    },
+                ));
+            }
+        }
 
         if tool == "finish_task"
             || tool == "finish_planning"
-            || (max_tool_calls != 0
-                && tool_calls.len() >= max_tool_calls as usize)
+            || (options.max_tool_calls != 0
+                && tool_calls.len() >= options.max_tool_calls as usize)
         {
             break;
         }
@@ -350,3 +446,28 @@ pub async fn loop_tools_until_finish(
     std::fs::write(&history_path, yaml_content).unwrap();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_touched_paths() {
+        let patch = "*** Begin Patch\n*** Update File: crates/a/src/lib.rs\n@@\n-x\n+y\n*** Add File: crates/b/new.rs\n+z\n*** End Patch";
+        assert_eq!(
+            touched_paths("apply_patch", &json!({ "patch": patch })),
+            vec!["crates/a/src/lib.rs", "crates/b/new.rs"]
+        );
+        assert_eq!(
+            touched_paths(
+                "search_files",
+                &json!({"pattern": "fn main", "path": "crates/c"})
+            ),
+            vec!["crates/c"]
+        );
+        assert!(
+            touched_paths("http_request", &json!({"path": "x"})).is_empty()
+        );
+    }
+}
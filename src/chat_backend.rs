@@ -0,0 +1,494 @@
+use async_openai::config::OpenAIConfig;
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionRequestSystemMessage,
+    ChatCompletionRequestSystemMessageContent, CreateChatCompletionRequest,
+    ResponseFormat, Stop,
+};
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde_json::{Value, json};
+use std::time::Duration;
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(600);
+
+pub struct ChatRequest {
+    pub model: String,
+    // System prompt blocks, sent as separate blocks where the API allows it.
+    pub system: Vec<String>,
+    pub messages: Vec<ChatCompletionRequestMessage>,
+    pub max_tokens: u32,
+    pub response_format: Option<ResponseFormat>,
+    pub stop: Vec<String>,
+    // Ask the API to cache the prompt prefix. Only the Anthropic API needs
+    // this; OpenAI-compatible APIs cache on their own or not at all.
+    pub cache_prompt: bool,
+}
+
+#[async_trait]
+pub trait ChatBackend: Send + Sync {
+    fn name(&self) -> &str;
+
+    // Returns the text of the model's reply.
+    async fn complete(&self, request: &ChatRequest) -> Result<String, String>;
+}
+
+// Role and text of a history message. Non-text content parts are dropped.
+fn role_and_text(message: &ChatCompletionRequestMessage) -> (String, String) {
+    let value = serde_json::to_value(message).unwrap_or_default();
+    let role = value["role"].as_str().unwrap_or("user").to_string();
+    let text = match &value["content"] {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|p| p["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    };
+    (role, text)
+}
+
+fn http_client(
+    headers: &[(String, String)],
+) -> Result<reqwest::Client, String> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| format!("invalid header name {}: {}", name, e))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| format!("invalid value for header {}: {}", name, e))?;
+        map.insert(name, value);
+    }
+    reqwest::Client::builder()
+        .default_headers(map)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+async fn post_json(
+    client: &reqwest::Client,
+    url: &str,
+    body: &Value,
+) -> Result<Value, String> {
+    let response =
+        client.post(url).json(body).send().await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    let text = response.text().await.map_err(|e| e.to_string())?;
+    let parsed: Value = serde_json::from_str(&text).unwrap_or(Value::Null);
+    if !status.is_success() {
+        let message = parsed["error"]["message"]
+            .as_str()
+            .or(parsed["error"].as_str())
+            .unwrap_or(&text);
+        return Err(format!("{} returned {}: {}", url, status, message));
+    }
+    if parsed.is_null() {
+        return Err(format!("{} returned invalid JSON: {}", url, text));
+    }
+    Ok(parsed)
+}
+
+// OpenAI's chat completions API, also spoken by OpenRouter, llama.cpp,
+// vLLM and most other servers.
+pub struct OpenAiBackend {
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub headers: Vec<(String, String)>,
+}
+
+#[async_trait]
+impl ChatBackend for OpenAiBackend {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    async fn complete(&self, request: &ChatRequest) -> Result<String, String> {
+        let client = async_openai::Client::with_config(
+            OpenAIConfig::new()
+                .with_api_base(&self.base_url)
+                .with_api_key(self.api_key.clone().unwrap_or_default()),
+        )
+        .with_http_client(http_client(&self.headers)?);
+        let mut messages = Vec::new();
+        if !request.system.is_empty() {
+            messages.push(ChatCompletionRequestMessage::System(
+                ChatCompletionRequestSystemMessage {
+                    content: ChatCompletionRequestSystemMessageContent::Text(
+                        request.system.join("\n\n"),
+                    ),
+                    name: None,
+                },
+            ));
+        }
+        messages.extend(request.messages.iter().cloned());
+        let body = CreateChatCompletionRequest {
+            model: request.model.clone(),
+            messages,
+            max_completion_tokens: Some(request.max_tokens),
+            response_format: request.response_format.clone(),
+            stop: (!request.stop.is_empty())
+                .then(|| Stop::StringArray(request.stop.clone())),
+            ..Default::default()
+        };
+        let response =
+            client.chat().create(body).await.map_err(|e| e.to_string())?;
+        let choice = response.choices.first().ok_or("No response")?;
+        Ok(choice.message.content.clone().ok_or("No content")?)
+    }
+}
+
+// The Anthropic Messages API (`/v1/messages`).
+pub struct AnthropicBackend {
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub headers: Vec<(String, String)>,
+}
+
+impl AnthropicBackend {
+    fn body(&self, request: &ChatRequest) -> Value {
+        let cache = json!({"type": "ephemeral"});
+        let mut system: Vec<Value> = request
+            .system
+            .iter()
+            .map(|text| json!({"type": "text", "text": text}))
+            .collect();
+        // Consecutive messages with the same role are merged into one
+        // message with several text blocks.
+        let mut messages: Vec<Value> = Vec::new();
+        for message in &request.messages {
+            let (role, text) = role_and_text(message);
+            if text.is_empty() {
+                continue;
+            }
+            let role = if role == "assistant" {
+                "assistant"
+            } else {
+                "user"
+            };
+            let block = json!({"type": "text", "text": text});
+            match messages.last_mut() {
+                Some(last) if last["role"] == role => {
+                    last["content"].as_array_mut().unwrap().push(block)
+                }
+                _ => messages.push(json!({"role": role, "content": [block]})),
+            }
+        }
+        // Cache breakpoints after the system prompt and after the latest
+        // message, so each turn reuses the prefix cached by the one before.
+        if request.cache_prompt {
+            if let Some(last) = system.last_mut() {
+                last["cache_control"] = cache.clone();
+            }
+            if let Some(block) = messages
+                .last_mut()
+                .and_then(|m| m["content"].as_array_mut())
+                .and_then(|blocks| blocks.last_mut())
+            {
+                block["cache_control"] = cache;
+            }
+        }
+        let mut body = json!({
+            "model": request.model,
+            "max_tokens": request.max_tokens,
+            "messages": messages,
+        });
+        if !system.is_empty() {
+            body["system"] = json!(system);
+        }
+        if !request.stop.is_empty() {
+            body["stop_sequences"] = json!(request.stop);
+        }
+        body
+    }
+}
+
+#[async_trait]
+impl ChatBackend for AnthropicBackend {
+    fn name(&self) -> &str {
+        "anthropic"
+    }
+
+    async fn complete(&self, request: &ChatRequest) -> Result<String, String> {
+        let mut headers = vec![(
+            "anthropic-version".to_string(),
+            ANTHROPIC_VERSION.to_string(),
+        )];
+        if let Some(key) = &self.api_key {
+            headers.push(("x-api-key".to_string(), key.clone()));
+        }
+        headers.extend(self.headers.iter().cloned());
+        let url =
+            format!("{}/v1/messages", self.base_url.trim_end_matches('/'));
+        let response =
+            post_json(&http_client(&headers)?, &url, &self.body(request))
+                .await?;
+        let text = response["content"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|block| block["type"] == "text")
+            .filter_map(|block| block["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("");
+        if text.is_empty() {
+            return Err("No content".to_string());
+        }
+        Ok(text)
+    }
+}
+
+// Ollama's native chat API (`/api/chat`), which unlike its OpenAI-compatible
+// endpoint accepts a JSON schema for structured output.
+pub struct OllamaBackend {
+    pub base_url: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl OllamaBackend {
+    fn body(&self, request: &ChatRequest) -> Value {
+        // Chat templates of many models keep only one system message.
+        let mut messages: Vec<Value> = Vec::new();
+        if !request.system.is_empty() {
+            messages.push(
+                json!({"role": "system", "content": request.system.join("\n\n")}),
+            );
+        }
+        for message in &request.messages {
+            let (role, text) = role_and_text(message);
+            messages.push(json!({"role": role, "content": text}));
+        }
+        let mut options = json!({"num_predict": request.max_tokens});
+        if !request.stop.is_empty() {
+            options["stop"] = json!(request.stop);
+        }
+        let mut body = json!({
+            "model": request.model,
+            "messages": messages,
+            "stream": false,
+            "options": options,
+        });
+        match &request.response_format {
+            Some(ResponseFormat::JsonSchema { json_schema }) => {
+                body["format"] = json_schema.schema.clone().unwrap_or_default()
+            }
+            Some(ResponseFormat::JsonObject) => body["format"] = json!("json"),
+            _ => {}
+        }
+        body
+    }
+}
+
+#[async_trait]
+impl ChatBackend for OllamaBackend {
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    async fn complete(&self, request: &ChatRequest) -> Result<String, String> {
+        let url = format!("{}/api/chat", self.base_url.trim_end_matches('/'));
+        let response =
+            post_json(&http_client(&self.headers)?, &url, &self.body(request))
+                .await?;
+        match response["message"]["content"].as_str() {
+            Some(text) if !text.is_empty() => Ok(text.to_string()),
+            _ => Err("No content".to_string()),
+        }
+    }
+}
+
+pub fn create(
+    api: &str,
+    base_url: Option<String>,
+    api_key: Option<String>,
+    headers: Vec<(String, String)>,
+) -> Result<Box<dyn ChatBackend>, String> {
+    Ok(match api {
+        "openai" => Box::new(OpenAiBackend {
+            base_url: base_url
+                .unwrap_or_else(|| "https://openrouter.ai/api/v1".to_string()),
+            api_key,
+            headers,
+        }),
+        "anthropic" => Box::new(AnthropicBackend {
+            base_url: base_url
+                .unwrap_or_else(|| "https://api.anthropic.com".to_string()),
+            api_key,
+            headers,
+        }),
+        "ollama" => Box::new(OllamaBackend {
+            base_url: base_url
+                .unwrap_or_else(|| "http://localhost:11434".to_string()),
+            headers,
+        }),
+        other => {
+            return Err(format!(
+                "unknown API `{}`; expected openai, anthropic or ollama",
+                other
+            ));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::test_server::{response, serve_once};
+    use async_openai::types::{
+        ChatCompletionRequestAssistantMessage,
+        ChatCompletionRequestAssistantMessageContent,
+        ChatCompletionRequestUserMessage,
+        ChatCompletionRequestUserMessageContent,
+    };
+
+    fn user(text: &str) -> ChatCompletionRequestMessage {
+        ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+            content: ChatCompletionRequestUserMessageContent::Text(
+                text.to_string(),
+            ),
+            name: None,
+        })
+    }
+
+    fn assistant(text: &str) -> ChatCompletionRequestMessage {
+        ChatCompletionRequestMessage::Assistant(
+            ChatCompletionRequestAssistantMessage {
+                content: Some(
+                    ChatCompletionRequestAssistantMessageContent::Text(
+                        text.to_string(),
+                    ),
+                ),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            model: "test-model".to_string(),
+            system: vec![
+                "You are a tool caller.".to_string(),
+                "Tools: none".to_string(),
+            ],
+            messages: vec![
+                user("AGENTS.md"),
+                user("list files"),
+                assistant("list_files: {}"),
+                user("[list_files .]\na.txt"),
+            ],
+            max_tokens: 100,
+            response_format: None,
+            stop: vec!["\n\n\n".to_string()],
+            cache_prompt: true,
+        }
+    }
+
+    // The JSON body of a raw HTTP request.
+    fn body_of(raw: &str) -> Value {
+        serde_json::from_str(raw.split("\r\n\r\n").nth(1).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_openai_backend() {
+        let reply = r#"{"id": "1", "object": "chat.completion", "created": 0, "model": "test-model",
+            "choices": [{"index": 0, "finish_reason": "stop",
+                "message": {"role": "assistant", "content": "finish_task: {}"}}]}"#;
+        let (url, server) =
+            serve_once(response("200 OK", "application/json", reply)).await;
+        let backend = OpenAiBackend {
+            base_url: url,
+            api_key: Some("sk-test".to_string()),
+            headers: vec![("X-Title".to_string(), "attotool".to_string())],
+        };
+        let text = backend.complete(&request()).await.unwrap();
+        assert_eq!(text, "finish_task: {}");
+        let raw = server.await.unwrap();
+        assert!(raw.starts_with("POST /chat/completions "));
+        let lower = raw.to_lowercase();
+        assert!(lower.contains("authorization: bearer sk-test"));
+        assert!(lower.contains("x-title: attotool"));
+        let body = body_of(&raw);
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(
+            body["messages"][0]["content"],
+            "You are a tool caller.\n\nTools: none"
+        );
+        assert_eq!(body["messages"].as_array().unwrap().len(), 5);
+        assert_eq!(body["stop"], json!(["\n\n\n"]));
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_backend() {
+        let reply = r#"{"id": "msg_1", "type": "message", "role": "assistant",
+            "content": [{"type": "text", "text": "finish_task:"}, {"type": "text", "text": " {}"}],
+            "stop_reason": "end_turn"}"#;
+        let (url, server) =
+            serve_once(response("200 OK", "application/json", reply)).await;
+        let backend = AnthropicBackend {
+            base_url: url,
+            api_key: Some("sk-ant".to_string()),
+            headers: Vec::new(),
+        };
+        let text = backend.complete(&request()).await.unwrap();
+        assert_eq!(text, "finish_task: {}");
+        let raw = server.await.unwrap();
+        assert!(raw.starts_with("POST /v1/messages "));
+        let lower = raw.to_lowercase();
+        assert!(lower.contains("x-api-key: sk-ant"));
+        assert!(lower.contains("anthropic-version: 2023-06-01"));
+        let body = body_of(&raw);
+        assert_eq!(
+            body["system"],
+            json!([{"type": "text", "text": "You are a tool caller."},
+                {"type": "text", "text": "Tools: none",
+                "cache_control": {"type": "ephemeral"}}])
+        );
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0]["content"].as_array().unwrap().len(), 2);
+        assert_eq!(messages[1]["role"], "assistant");
+        assert_eq!(
+            messages[2]["content"][0]["cache_control"],
+            json!({"type": "ephemeral"})
+        );
+        assert_eq!(body["stop_sequences"], json!(["\n\n\n"]));
+
+        let error = r#"{"type": "error", "error": {"type": "authentication_error", "message": "invalid x-api-key"}}"#;
+        let (url, server) =
+            serve_once(response("401 Unauthorized", "application/json", error))
+                .await;
+        let backend = AnthropicBackend {
+            base_url: url,
+            api_key: None,
+            headers: Vec::new(),
+        };
+        let err = backend.complete(&request()).await.unwrap_err();
+        server.await.unwrap();
+        assert!(err.ends_with("401 Unauthorized: invalid x-api-key"));
+    }
+
+    #[tokio::test]
+    async fn test_ollama_backend() {
+        let reply = r#"{"model": "test-model", "done": true,
+            "message": {"role": "assistant", "content": "{\"tool\": \"finish_task\"}"}}"#;
+        let (url, server) =
+            serve_once(response("200 OK", "application/json", reply)).await;
+        let backend = OllamaBackend {
+            base_url: url,
+            headers: Vec::new(),
+        };
+        let mut request = request();
+        request.response_format = Some(ResponseFormat::JsonObject);
+        let text = backend.complete(&request).await.unwrap();
+        assert_eq!(text, "{\"tool\": \"finish_task\"}");
+        let raw = server.await.unwrap();
+        assert!(raw.starts_with("POST /api/chat "));
+        let body = body_of(&raw);
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["format"], "json");
+        assert_eq!(body["options"]["num_predict"], 100);
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][3]["role"], "assistant");
+    }
+}
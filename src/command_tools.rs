@@ -0,0 +1,279 @@
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+
+fn default_kind() -> String {
+    "string".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_timeout() -> u64 {
+    120
+}
+
+#[derive(Clone, Deserialize)]
+pub struct CommandParam {
+    pub name: String,
+    // One of string, integer or boolean.
+    #[serde(rename = "type", default = "default_kind")]
+    pub kind: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub optional: bool,
+    // When non-empty, the only values the model may pass.
+    #[serde(default)]
+    pub choices: Vec<String>,
+}
+
+// A project-specific tool declared in config.yaml that runs a command
+// template such as `cargo test -p {crate} -- {filter}`. Arguments are
+// shell-quoted before they are substituted.
+#[derive(Clone, Deserialize)]
+pub struct CommandTool {
+    pub name: String,
+    pub description: String,
+    #[serde(default)]
+    pub params: Vec<CommandParam>,
+    pub command: String,
+    #[serde(default = "default_true")]
+    pub require_approval: bool,
+    // Read-only tools are also offered in plan mode.
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+}
+
+enum Piece<'a> {
+    Text(String),
+    Param(&'a str),
+}
+
+// Splits a template into literal text and `{param}` placeholders; `{{` and
+// `}}` stand for literal braces.
+fn parse_template(template: &str) -> Result<Vec<Piece<'_>>, String> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            text.push(c);
+            rest = &rest[2..];
+        } else if c == '{' {
+            let end = rest
+                .find('}')
+                .ok_or_else(|| format!("unclosed `{{` in `{}`", template))?;
+            pieces.push(Piece::Text(std::mem::take(&mut text)));
+            pieces.push(Piece::Param(rest[1..end].trim()));
+            rest = &rest[end + 1..];
+        } else if c == '}' {
+            return Err(format!("unmatched `}}` in `{}`", template));
+        } else {
+            text.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    pieces.push(Piece::Text(text));
+    Ok(pieces)
+}
+
+pub fn shell_quote(value: &str) -> String {
+    let safe = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./:=@%+,".contains(c));
+    if safe {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+impl CommandTool {
+    // Checks the template against the declared parameters so mistakes in
+    // config.yaml are reported at startup instead of on first use.
+    pub fn validate(&self) -> Result<(), String> {
+        for piece in parse_template(&self.command)? {
+            if let Piece::Param(name) = piece
+                && !self.params.iter().any(|p| p.name == name)
+            {
+                return Err(format!("`{{{}}}` is not a parameter", name));
+            }
+        }
+        for param in &self.params {
+            if !matches!(param.kind.as_str(), "string" | "integer" | "boolean")
+            {
+                return Err(format!(
+                    "parameter `{}` has unknown type `{}`",
+                    param.name, param.kind
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn parameters(&self) -> Vec<(String, String)> {
+        self.params
+            .iter()
+            .map(|param| {
+                let mut kind = param.kind.clone();
+                if param.optional {
+                    kind.push_str(" (optional)");
+                }
+                if !param.choices.is_empty() {
+                    kind.push_str(&format!(
+                        " - one of: {}",
+                        param.choices.join(", ")
+                    ));
+                }
+                if !param.description.is_empty() {
+                    kind.push_str(&format!(" - {}", param.description));
+                }
+                (param.name.clone(), kind)
+            })
+            .collect()
+    }
+
+    pub fn input_schema(&self) -> Value {
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+        for param in &self.params {
+            let mut schema = json!({"type": param.kind});
+            if !param.choices.is_empty() {
+                schema["enum"] = json!(param.choices);
+            }
+            if !param.description.is_empty() {
+                schema["description"] = json!(param.description);
+            }
+            if !param.optional {
+                required.push(Value::String(param.name.clone()));
+            }
+            properties.insert(param.name.clone(), schema);
+        }
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+            "additionalProperties": false
+        })
+    }
+
+    fn argument(
+        &self,
+        param: &CommandParam,
+        args: &Value,
+    ) -> Result<String, String> {
+        let value = match &args[&param.name] {
+            Value::Null if param.optional => return Ok(String::new()),
+            Value::Null => {
+                return Err(format!("missing argument `{}`", param.name));
+            }
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        let valid = match param.kind.as_str() {
+            "integer" => value.trim().parse::<i64>().is_ok(),
+            "boolean" => matches!(value.as_str(), "true" | "false"),
+            _ => true,
+        };
+        if !valid {
+            return Err(format!(
+                "`{}` must be {} {}, got `{}`",
+                param.name,
+                if param.kind == "integer" { "an" } else { "a" },
+                param.kind,
+                value
+            ));
+        }
+        if !param.choices.is_empty() && !param.choices.contains(&value) {
+            return Err(format!(
+                "`{}` must be one of {}, got `{}`",
+                param.name,
+                param.choices.join(", "),
+                value
+            ));
+        }
+        Ok(shell_quote(value.trim()))
+    }
+
+    // Fills in the template; optional arguments that were left out expand to
+    // nothing.
+    pub fn render(&self, args: &Value) -> Result<String, String> {
+        let mut command = String::new();
+        for piece in parse_template(&self.command)? {
+            match piece {
+                Piece::Text(text) => command.push_str(&text),
+                Piece::Param(name) => {
+                    let param = self
+                        .params
+                        .iter()
+                        .find(|p| p.name == name)
+                        .ok_or_else(|| {
+                            format!("unknown parameter `{}`", name)
+                        })?;
+                    command.push_str(&self.argument(param, args)?);
+                }
+            }
+        }
+        Ok(command.trim().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cargo_test_tool() -> CommandTool {
+        serde_yaml::from_str(
+            r#"
+name: run_tests
+description: Run the tests of one crate
+params:
+  - name: crate
+    choices: [core, cli]
+  - name: jobs
+    type: integer
+  - name: filter
+    optional: true
+command: cargo test -p {crate} --jobs {jobs} -- {filter}
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_render_quotes_and_validates_arguments() {
+        let tool = cargo_test_tool();
+        assert!(tool.validate().is_ok());
+        assert!(tool.require_approval);
+        assert_eq!(
+            tool.render(&json!({
+                "crate": "core",
+                "jobs": 4,
+                "filter": "it's; rm -rf /"
+            }))
+            .unwrap(),
+            "cargo test -p core --jobs 4 -- 'it'\\''s; rm -rf /'"
+        );
+        assert_eq!(
+            tool.render(&json!({"crate": "cli", "jobs": "2"})).unwrap(),
+            "cargo test -p cli --jobs 2 --"
+        );
+        assert!(tool.render(&json!({"crate": "other", "jobs": 1})).is_err());
+        assert!(tool.render(&json!({"jobs": 1})).is_err());
+        assert!(tool.render(&json!({"crate": "cli", "jobs": "many"})).is_err());
+        assert_eq!(
+            tool.input_schema()["properties"]["crate"]["enum"],
+            json!(["core", "cli"])
+        );
+
+        let mut broken = cargo_test_tool();
+        broken.command = "cargo test -p {package}".to_string();
+        assert!(broken.validate().is_err());
+        broken.command = "echo {{literal}}".to_string();
+        assert_eq!(broken.render(&json!({})).unwrap(), "echo {literal}");
+    }
+}
@@ -0,0 +1,510 @@
+use similar::TextDiff;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const UTF8_BOM: &str = "\u{feff}";
+
+// How a text file is laid out on disk, so that edits made on LF-normalised
+// text can be written back without touching anything else.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextFormat {
+    pub bom: bool,
+    pub crlf: bool,
+    pub final_newline: bool,
+}
+
+impl Default for TextFormat {
+    fn default() -> Self {
+        TextFormat {
+            bom: false,
+            crlf: false,
+            final_newline: true,
+        }
+    }
+}
+
+impl TextFormat {
+    pub fn detect(raw: &str) -> Self {
+        let crlf_count = raw.matches("\r\n").count();
+        let lf_count = raw.matches('\n').count() - crlf_count;
+        TextFormat {
+            bom: raw.starts_with(UTF8_BOM),
+            crlf: crlf_count > lf_count,
+            final_newline: raw.is_empty() || raw.ends_with('\n'),
+        }
+    }
+
+    // Splits raw file contents into LF-only text (with a trailing newline
+    // whenever there are lines) and the detected format.
+    pub fn normalize(raw: &str) -> (String, Self) {
+        let format = TextFormat::detect(raw);
+        let body = raw.strip_prefix(UTF8_BOM).unwrap_or(raw);
+        let mut text = body.replace("\r\n", "\n");
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        (text, format)
+    }
+
+    pub fn render(&self, text: &str) -> String {
+        let mut body = text.replace("\r\n", "\n");
+        if !self.final_newline {
+            while body.ends_with('\n') {
+                body.pop();
+            }
+        } else if !body.is_empty() && !body.ends_with('\n') {
+            body.push('\n');
+        }
+        if self.crlf {
+            body = body.replace('\n', "\r\n");
+        }
+        if self.bom {
+            body.insert_str(0, UTF8_BOM);
+        }
+        body
+    }
+}
+
+pub enum LineEdit {
+    Replace { start: usize, end: usize },
+    InsertBefore { line: usize },
+    Append,
+    Delete { start: usize, end: usize },
+}
+
+// Applies a line-based edit to LF-normalised `text`. Line numbers are
+// 1-based and inclusive. Returns the new text and the 1-based line range
+// of the edited region in it (empty when lines were only removed).
+pub fn apply_line_edit(
+    text: &str,
+    edit: &LineEdit,
+    content: &str,
+) -> Result<(String, usize, usize), String> {
+    let mut lines: Vec<&str> = text.lines().collect();
+    let new_lines: Vec<&str> = content.lines().collect();
+    let check_range = |start: usize, end: usize, len: usize| {
+        if start == 0 || end < start {
+            Err(format!("Invalid line range {}-{}", start, end))
+        } else if start > len {
+            Err(format!(
+                "Start line {} out of bounds (the file has {} lines)",
+                start, len
+            ))
+        } else {
+            Ok(())
+        }
+    };
+    let (position, removed) = match *edit {
+        LineEdit::Replace { start, end } => {
+            // Replacing just past the end is allowed and appends.
+            check_range(start, end, lines.len() + 1)?;
+            (start - 1, end.min(lines.len()).saturating_sub(start - 1))
+        }
+        LineEdit::InsertBefore { line } => {
+            check_range(line, line, lines.len() + 1)?;
+            (line - 1, 0)
+        }
+        LineEdit::Append => (lines.len(), 0),
+        LineEdit::Delete { start, end } => {
+            check_range(start, end, lines.len())?;
+            (start - 1, end.min(lines.len()) - (start - 1))
+        }
+    };
+    let inserted = if matches!(edit, LineEdit::Delete { .. }) {
+        Vec::new()
+    } else {
+        new_lines
+    };
+    let inserted_len = inserted.len();
+    lines.splice(position..position + removed, inserted);
+    let mut result = lines.join("\n");
+    if !lines.is_empty() {
+        result.push('\n');
+    }
+    Ok((result, position + 1, position + inserted_len))
+}
+
+// The file that writing to `path` replaces: the target of a symlink, or
+// `path` itself.
+fn write_target(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn stage_temp(path: &Path, content: &str) -> io::Result<PathBuf> {
+    let temp = temp_path(path);
+    fs::write(&temp, content)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(&temp, metadata.permissions()).ok();
+    }
+    Ok(temp)
+}
+
+// Writes through a temporary file and a rename so readers never see a
+// half-written file. Permissions are carried over and symlinks are followed.
+pub fn write_atomic(path: &Path, content: &str) -> io::Result<()> {
+    let target = write_target(path);
+    let temp = stage_temp(&target, content)?;
+    fs::rename(&temp, &target).inspect_err(|_| {
+        fs::remove_file(&temp).ok();
+    })
+}
+
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", path), &format!("b/{}", path))
+        .to_string()
+}
+
+#[derive(Debug)]
+pub struct Replacement {
+    pub content: String,
+    pub count: usize,
+    // 1-based line range covering the first replaced region in `content`
+    pub first_line: usize,
+    pub last_line: usize,
+}
+
+pub fn replace_in_text(
+    content: &str,
+    old_string: &str,
+    new_string: &str,
+    replace_all: bool,
+) -> Result<Replacement, String> {
+    if old_string.is_empty() {
+        return Err("old_string must not be empty".to_string());
+    }
+    if old_string == new_string {
+        return Err("old_string and new_string are identical".to_string());
+    }
+    let count = content.matches(old_string).count();
+    if count == 0 {
+        let hint = if content.contains(old_string.trim()) {
+            " (a whitespace-trimmed version does match; check indentation and line breaks)"
+        } else {
+            ""
+        };
+        return Err(format!("old_string was not found in the file{}", hint));
+    }
+    if count > 1 && !replace_all {
+        return Err(format!(
+            "old_string matches {} times; include more surrounding lines to make it unique, or set replace_all to true",
+            count
+        ));
+    }
+    let offset = content.find(old_string).unwrap_or(0);
+    let first_line = content[..offset].matches('\n').count() + 1;
+    let last_line =
+        first_line + new_string.trim_end_matches('\n').matches('\n').count();
+    let content = if replace_all {
+        content.replace(old_string, new_string)
+    } else {
+        content.replacen(old_string, new_string, 1)
+    };
+    Ok(Replacement {
+        content,
+        count,
+        first_line,
+        last_line,
+    })
+}
+
+// Renders lines first..=last of `content` (1-based) plus `context` lines on
+// each side, prefixed with their line numbers.
+pub fn numbered_snippet(
+    content: &str,
+    first: usize,
+    last: usize,
+    context: usize,
+) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    if lines.is_empty() {
+        return String::new();
+    }
+    let start = first.saturating_sub(context).max(1);
+    let end = (last + context).min(lines.len());
+    (start..=end)
+        .map(|n| format!("{:>6}\t{}", n, lines[n - 1]))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// A pending change to one file. `original` is None when the file does not
+// exist yet, `updated` is None when the file is to be deleted.
+pub struct FileChange {
+    pub path: PathBuf,
+    pub original: Option<String>,
+    pub updated: Option<String>,
+}
+
+impl FileChange {
+    pub fn summary(&self) -> String {
+        let action = match (&self.original, &self.updated) {
+            (None, _) => "added",
+            (Some(_), None) => "deleted",
+            (Some(_), Some(_)) => "updated",
+        };
+        format!("{} {}", action, self.path.display())
+    }
+}
+
+pub fn combined_diff(changes: &[FileChange]) -> String {
+    changes
+        .iter()
+        .map(|c| {
+            unified_diff(
+                &c.path.display().to_string(),
+                c.original.as_deref().unwrap_or(""),
+                c.updated.as_deref().unwrap_or(""),
+            )
+        })
+        .collect()
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.attotool-tmp", name))
+}
+
+// Applies every change or none of them. New contents are first written to
+// temporary files next to their targets; only once all of them exist are
+// they renamed into place. A failure while renaming restores the files that
+// were already replaced.
+pub fn commit_file_changes(changes: &[FileChange]) -> Result<(), String> {
+    let mut staged: Vec<PathBuf> = Vec::new();
+    let cleanup = |staged: &[PathBuf]| {
+        for temp in staged {
+            fs::remove_file(temp).ok();
+        }
+    };
+    for change in changes {
+        let Some(updated) = &change.updated else {
+            continue;
+        };
+        if let Some(parent) = change.path.parent()
+            && !parent.as_os_str().is_empty()
+            && let Err(e) = fs::create_dir_all(parent)
+        {
+            cleanup(&staged);
+            return Err(format!(
+                "failed to create {}: {}",
+                parent.display(),
+                e
+            ));
+        }
+        match stage_temp(&write_target(&change.path), updated) {
+            Ok(temp) => staged.push(temp),
+            Err(e) => {
+                cleanup(&staged);
+                return Err(format!(
+                    "failed to write {}: {}",
+                    change.path.display(),
+                    e
+                ));
+            }
+        }
+    }
+
+    for (index, change) in changes.iter().enumerate() {
+        let result = match change.updated {
+            Some(_) => {
+                let target = write_target(&change.path);
+                fs::rename(temp_path(&target), &target)
+            }
+            None => fs::remove_file(&change.path),
+        };
+        if let Err(e) = result {
+            for done in &changes[..index] {
+                match &done.original {
+                    Some(original) => fs::write(&done.path, original).ok(),
+                    None => fs::remove_file(&done.path).ok(),
+                };
+            }
+            cleanup(&staged);
+            return Err(format!(
+                "failed to update {}: {}; no files were changed",
+                change.path.display(),
+                e
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_in_text_unique_match() {
+        let content = "fn a() {\n    1\n}\nfn b() {\n    2\n}\n";
+        let result =
+            replace_in_text(content, "    2\n", "    20\n    21\n", false)
+                .unwrap();
+        assert_eq!(
+            result.content,
+            "fn a() {\n    1\n}\nfn b() {\n    20\n    21\n}\n"
+        );
+        assert_eq!(result.count, 1);
+        assert_eq!((result.first_line, result.last_line), (5, 6));
+        assert_eq!(
+            numbered_snippet(&result.content, 5, 6, 1),
+            "     4\tfn b() {\n     5\t    20\n     6\t    21\n     7\t}"
+        );
+    }
+
+    #[test]
+    fn test_replace_in_text_missing_and_ambiguous() {
+        let content = "x = 1\nx = 1\n";
+        let err =
+            replace_in_text(content, "y = 1", "y = 2", false).unwrap_err();
+        assert!(err.contains("not found"));
+        let err =
+            replace_in_text(content, "x = 1", "x = 2", false).unwrap_err();
+        assert!(err.contains("matches 2 times"));
+        let result = replace_in_text(content, "x = 1", "x = 2", true).unwrap();
+        assert_eq!(result.content, "x = 2\nx = 2\n");
+        assert_eq!(result.count, 2);
+    }
+
+    #[test]
+    fn test_unified_diff_has_headers_and_hunks() {
+        let diff = unified_diff("f.txt", "a\nb\n", "a\nc\n");
+        assert_eq!(
+            diff,
+            "--- a/f.txt\n+++ b/f.txt\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n"
+        );
+    }
+
+    #[test]
+    fn test_commit_file_changes_rolls_back_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let kept = dir.path().join("kept.txt");
+        fs::write(&kept, "original\n").unwrap();
+        let changes = vec![
+            FileChange {
+                path: kept.clone(),
+                original: Some("original\n".to_string()),
+                updated: Some("changed\n".to_string()),
+            },
+            FileChange {
+                path: dir.path().join("missing.txt"),
+                original: Some("gone\n".to_string()),
+                updated: None,
+            },
+        ];
+        let err = commit_file_changes(&changes).unwrap_err();
+        assert!(err.contains("no files were changed"));
+        assert_eq!(fs::read_to_string(&kept).unwrap(), "original\n");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_commit_file_changes_applies_all() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = dir.path().join("old.txt");
+        fs::write(&old, "bye\n").unwrap();
+        let changes = vec![
+            FileChange {
+                path: dir.path().join("nested/new.txt"),
+                original: None,
+                updated: Some("hello\n".to_string()),
+            },
+            FileChange {
+                path: old.clone(),
+                original: Some("bye\n".to_string()),
+                updated: None,
+            },
+        ];
+        commit_file_changes(&changes).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("nested/new.txt")).unwrap(),
+            "hello\n"
+        );
+        assert!(!old.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_commit_file_changes_follows_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().join("real.txt");
+        let link = dir.path().join("link.txt");
+        fs::write(&real, "before\n").unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+        commit_file_changes(&[FileChange {
+            path: link.clone(),
+            original: Some("before\n".to_string()),
+            updated: Some("after\n".to_string()),
+        }])
+        .unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&real).unwrap(), "after\n");
+    }
+
+    #[test]
+    fn test_text_format_round_trip_crlf_bom_without_final_newline() {
+        let raw = "\u{feff}one\r\ntwo\r\nthree";
+        let (text, format) = TextFormat::normalize(raw);
+        assert_eq!(text, "one\ntwo\nthree\n");
+        assert_eq!(
+            format,
+            TextFormat {
+                bom: true,
+                crlf: true,
+                final_newline: false
+            }
+        );
+        assert_eq!(format.render(&text), raw);
+        assert_eq!(
+            format.render("one\nTWO\nthree\n"),
+            "\u{feff}one\r\nTWO\r\nthree"
+        );
+    }
+
+    #[test]
+    fn test_apply_line_edit_operations() {
+        let text = "a\nb\nc\n";
+        let replace = LineEdit::Replace { start: 2, end: 2 };
+        assert_eq!(
+            apply_line_edit(text, &replace, "B1\nB2").unwrap(),
+            ("a\nB1\nB2\nc\n".to_string(), 2, 3)
+        );
+        let insert = LineEdit::InsertBefore { line: 1 };
+        assert_eq!(
+            apply_line_edit(text, &insert, "top").unwrap(),
+            ("top\na\nb\nc\n".to_string(), 1, 1)
+        );
+        assert_eq!(
+            apply_line_edit(text, &LineEdit::Append, "d").unwrap(),
+            ("a\nb\nc\nd\n".to_string(), 4, 4)
+        );
+        let delete = LineEdit::Delete { start: 1, end: 2 };
+        assert_eq!(
+            apply_line_edit(text, &delete, "").unwrap(),
+            ("c\n".to_string(), 1, 0)
+        );
+        let out_of_bounds = LineEdit::Delete { start: 4, end: 4 };
+        assert!(apply_line_edit(text, &out_of_bounds, "").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_preserves_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("script.sh");
+        fs::write(&path, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        write_atomic(&path, "#!/bin/sh\necho hi\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "#!/bin/sh\necho hi\n");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
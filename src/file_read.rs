@@ -0,0 +1,173 @@
+use std::fs;
+use std::path::Path;
+
+pub const DEFAULT_LINE_LIMIT: usize = 2000;
+const MAX_LINE_CHARS: usize = 2000;
+const MAX_PAGE_BYTES: usize = 100_000;
+const BINARY_SNIFF_BYTES: usize = 8192;
+
+#[derive(Debug)]
+pub struct Page {
+    // Lines prefixed with their 1-based line numbers.
+    pub text: String,
+    pub first: usize,
+    pub last: usize,
+    pub total: usize,
+    // Set when the file is not plain UTF-8 and was decoded or repaired.
+    pub encoding_note: Option<String>,
+}
+
+impl Page {
+    pub fn has_more(&self) -> bool {
+        self.last < self.total
+    }
+}
+
+enum Decoded {
+    Text(String, Option<String>),
+    Binary,
+}
+
+fn decode_utf16(bytes: &[u8], big_endian: bool) -> String {
+    let units = bytes.chunks_exact(2).map(|pair| {
+        if big_endian {
+            u16::from_be_bytes([pair[0], pair[1]])
+        } else {
+            u16::from_le_bytes([pair[0], pair[1]])
+        }
+    });
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+fn decode(bytes: &[u8]) -> Decoded {
+    if let Some(rest) = bytes.strip_prefix(&[0xff, 0xfe]) {
+        let note = "the file is UTF-16 (little endian) and was converted";
+        return Decoded::Text(decode_utf16(rest, false), Some(note.into()));
+    }
+    if let Some(rest) = bytes.strip_prefix(&[0xfe, 0xff]) {
+        let note = "the file is UTF-16 (big endian) and was converted";
+        return Decoded::Text(decode_utf16(rest, true), Some(note.into()));
+    }
+    let bytes = bytes.strip_prefix(&[0xef, 0xbb, 0xbf]).unwrap_or(bytes);
+    let sniff = &bytes[..bytes.len().min(BINARY_SNIFF_BYTES)];
+    if sniff.contains(&0) {
+        return Decoded::Binary;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Decoded::Text(text.to_string(), None),
+        Err(_) => Decoded::Text(
+            String::from_utf8_lossy(bytes).into_owned(),
+            Some(
+                "the file is not valid UTF-8; invalid bytes are shown as \u{fffd}"
+                    .into(),
+            ),
+        ),
+    }
+}
+
+fn truncate_line(line: &str) -> String {
+    match line.char_indices().nth(MAX_LINE_CHARS) {
+        Some((index, _)) => format!(
+            "{}[... line truncated, {} more characters]",
+            &line[..index],
+            line[index..].chars().count()
+        ),
+        None => line.to_string(),
+    }
+}
+
+// Reads up to `limit` lines of `path` starting at line `offset` (1-based).
+// Pages also stop early once they reach MAX_PAGE_BYTES, so callers should
+// rely on `last` rather than on `limit` when asking for the next page.
+pub fn read_page(
+    path: &Path,
+    offset: usize,
+    limit: usize,
+) -> Result<Page, String> {
+    let bytes =
+        fs::read(path).map_err(|e| format!("Error reading file: {}", e))?;
+    let (content, encoding_note) = match decode(&bytes) {
+        Decoded::Text(content, note) => (content, note),
+        Decoded::Binary => {
+            return Err(format!(
+                "{} appears to be a binary file ({} bytes); its contents are not shown",
+                path.display(),
+                bytes.len()
+            ));
+        }
+    };
+    let lines: Vec<&str> = content.lines().collect();
+    let first = offset.max(1);
+    if first > lines.len() && !(lines.is_empty() && first == 1) {
+        return Err(format!(
+            "Line {} is past the end of the file ({} lines)",
+            first,
+            lines.len()
+        ));
+    }
+    let mut text = String::new();
+    let mut last = first - 1;
+    for (index, line) in
+        lines.iter().enumerate().skip(first - 1).take(limit.max(1))
+    {
+        let numbered = format!("{:>6}\t{}\n", index + 1, truncate_line(line));
+        if !text.is_empty() && text.len() + numbered.len() > MAX_PAGE_BYTES {
+            break;
+        }
+        text.push_str(&numbered);
+        last = index + 1;
+    }
+    text.pop();
+    Ok(Page {
+        text,
+        first,
+        last,
+        total: lines.len(),
+        encoding_note,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_page_numbers_and_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        fs::write(&path, "one\ntwo\nthree\nfour\n").unwrap();
+        let page = read_page(&path, 2, 2).unwrap();
+        assert_eq!(page.text, "     2\ttwo\n     3\tthree");
+        assert_eq!((page.first, page.last, page.total), (2, 3, 4));
+        assert!(page.has_more());
+        assert!(!read_page(&path, 4, 10).unwrap().has_more());
+        assert!(read_page(&path, 5, 10).is_err());
+    }
+
+    #[test]
+    fn test_read_page_detects_binary_and_encodings() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("blob.bin");
+        fs::write(&binary, [0x7f, b'E', b'L', b'F', 0, 0, 1]).unwrap();
+        let err = read_page(&binary, 1, 10).unwrap_err();
+        assert!(err.contains("binary file (7 bytes)"));
+
+        let utf16 = dir.path().join("utf16.txt");
+        let mut bytes = vec![0xff, 0xfe];
+        for unit in "hi\nthere\n".encode_utf16() {
+            bytes.extend(unit.to_le_bytes());
+        }
+        fs::write(&utf16, bytes).unwrap();
+        let page = read_page(&utf16, 1, 10).unwrap();
+        assert_eq!(page.text, "     1\thi\n     2\tthere");
+        assert!(page.encoding_note.unwrap().contains("UTF-16"));
+
+        let latin1 = dir.path().join("latin1.txt");
+        fs::write(&latin1, b"caf\xe9\n").unwrap();
+        let page = read_page(&latin1, 1, 10).unwrap();
+        assert_eq!(page.text, "     1\tcaf\u{fffd}");
+        assert!(page.encoding_note.is_some());
+    }
+}
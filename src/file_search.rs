@@ -0,0 +1,605 @@
+use globset::{Glob, GlobMatcher};
+use ignore::WalkBuilder;
+use regex::RegexBuilder;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Larger files are skipped by search_files, which reads each file whole.
+const MAX_SEARCH_FILE_BYTES: u64 = 4 * 1024 * 1024;
+
+pub struct ListOptions {
+    pub depth: usize,
+    pub tree: bool,
+    pub glob: Option<String>,
+    pub sizes: bool,
+    pub max_entries: usize,
+}
+
+pub struct SearchOptions {
+    pub literal: bool,
+    pub case_insensitive: bool,
+    pub include: Option<String>,
+    pub exclude: Option<String>,
+    pub context: usize,
+    pub max_results: usize,
+}
+
+#[derive(PartialEq)]
+pub enum FindSort {
+    Name,
+    Modified,
+}
+
+struct Entry {
+    relative: PathBuf,
+    depth: usize,
+    is_dir: bool,
+    size: u64,
+}
+
+// Walks `root` the way git sees it: .gitignore, .ignore and global excludes
+// are honoured, hidden files are skipped, even outside a git repository.
+fn walker(root: &Path, max_depth: Option<usize>) -> ignore::Walk {
+    WalkBuilder::new(root)
+        .max_depth(max_depth)
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build()
+}
+
+fn glob_matcher(
+    pattern: &str,
+) -> Result<GlobMatcher, Box<dyn std::error::Error>> {
+    Ok(Glob::new(pattern)
+        .map_err(|e| format!("Invalid glob '{}': {}", pattern, e))?
+        .compile_matcher())
+}
+
+// Patterns without a slash match file names anywhere, like .gitignore does.
+fn glob_matches(matcher: &GlobMatcher, pattern: &str, relative: &Path) -> bool {
+    if pattern.contains('/') {
+        matcher.is_match(relative)
+    } else {
+        relative
+            .file_name()
+            .is_some_and(|name| matcher.is_match(Path::new(name)))
+    }
+}
+
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", size)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+pub fn list_files(
+    root: &Path,
+    options: &ListOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if !root.is_dir() {
+        return Err(format!("Not a directory: {}", root.display()).into());
+    }
+    let matcher = match &options.glob {
+        Some(pattern) => Some(glob_matcher(pattern)?),
+        None => None,
+    };
+
+    let mut entries = Vec::new();
+    for result in walker(root, Some(options.depth.max(1))) {
+        let entry = match result {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if entry.depth() == 0 {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(root)?.to_path_buf();
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+        let size = if options.sizes && !is_dir {
+            entry.metadata().map(|m| m.len()).unwrap_or(0)
+        } else {
+            0
+        };
+        entries.push(Entry {
+            relative,
+            depth: entry.depth(),
+            is_dir,
+            size,
+        });
+    }
+
+    if let (Some(matcher), Some(pattern)) = (&matcher, &options.glob) {
+        let matched: Vec<PathBuf> = entries
+            .iter()
+            .filter(|e| {
+                !e.is_dir && glob_matches(matcher, pattern, &e.relative)
+            })
+            .map(|e| e.relative.clone())
+            .collect();
+        // Keep matching files plus the directories leading to them.
+        entries.retain(|e| {
+            if e.is_dir {
+                matched.iter().any(|m| m.starts_with(&e.relative))
+            } else {
+                matched.contains(&e.relative)
+            }
+        });
+    }
+
+    if entries.is_empty() {
+        return Ok(format!("No entries found in {}", root.display()));
+    }
+
+    let total = entries.len();
+    let lines = if options.tree {
+        render_tree(&entries, options.sizes)
+    } else {
+        entries.iter().map(|e| render_flat(e, options.sizes)).collect()
+    };
+    let mut result = lines
+        .into_iter()
+        .take(options.max_entries)
+        .collect::<Vec<_>>()
+        .join("\n");
+    if total > options.max_entries {
+        result.push_str(&format!(
+            "\n[... {} more entries not shown; narrow the path, lower depth or use glob]",
+            total - options.max_entries
+        ));
+    }
+    Ok(result)
+}
+
+fn render_flat(entry: &Entry, sizes: bool) -> String {
+    let mut line = entry.relative.display().to_string();
+    if entry.is_dir {
+        line.push('/');
+    } else if sizes {
+        line.push_str(&format!(" ({})", format_size(entry.size)));
+    }
+    line
+}
+
+fn render_tree(entries: &[Entry], sizes: bool) -> Vec<String> {
+    // An entry is the last of its siblings when no later entry at the same
+    // depth appears before the walk climbs back above it.
+    let mut is_last = vec![true; entries.len()];
+    for i in 0..entries.len() {
+        for later in &entries[i + 1..] {
+            if later.depth < entries[i].depth {
+                break;
+            }
+            if later.depth == entries[i].depth {
+                is_last[i] = false;
+                break;
+            }
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut open_levels: Vec<bool> = Vec::new();
+    for (entry, last) in entries.iter().zip(is_last) {
+        open_levels.truncate(entry.depth - 1);
+        let mut line: String = open_levels
+            .iter()
+            .map(|open| if *open { "│   " } else { "    " })
+            .collect();
+        line.push_str(if last { "└── " } else { "├── " });
+        let name = entry
+            .relative
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        line.push_str(&name);
+        if entry.is_dir {
+            line.push('/');
+        } else if sizes {
+            line.push_str(&format!(" ({})", format_size(entry.size)));
+        }
+        lines.push(line);
+        open_levels.push(!last);
+    }
+    lines
+}
+
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes.iter().take(8192).any(|b| *b == 0)
+}
+
+pub fn search_files(
+    root: &Path,
+    pattern: &str,
+    options: &SearchOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if pattern.is_empty() {
+        return Err("pattern must not be empty".into());
+    }
+    let source = if options.literal {
+        regex::escape(pattern)
+    } else {
+        pattern.to_string()
+    };
+    let regex = RegexBuilder::new(&source)
+        .case_insensitive(options.case_insensitive)
+        .build()
+        .map_err(|e| format!("Invalid regex: {}", e))?;
+    let include = match &options.include {
+        Some(glob) => Some((glob_matcher(glob)?, glob.as_str())),
+        None => None,
+    };
+    let exclude = match &options.exclude {
+        Some(glob) => Some((glob_matcher(glob)?, glob.as_str())),
+        None => None,
+    };
+
+    let mut output = Vec::new();
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    let mut skipped = Vec::new();
+    let mut total = 0;
+    for result in walker(root, None) {
+        let entry = match result {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        // A search rooted at a single file reports that file's own path.
+        let relative = match entry.path().strip_prefix(root) {
+            Ok(r) if !r.as_os_str().is_empty() => r.to_path_buf(),
+            _ => entry.path().to_path_buf(),
+        };
+        if include.as_ref().is_some_and(|(m, p)| !glob_matches(m, p, &relative))
+            || exclude
+                .as_ref()
+                .is_some_and(|(m, p)| glob_matches(m, p, &relative))
+        {
+            continue;
+        }
+        if entry.metadata().is_ok_and(|m| m.len() > MAX_SEARCH_FILE_BYTES) {
+            skipped.push(relative.display().to_string());
+            continue;
+        }
+        let bytes = match fs::read(entry.path()) {
+            Ok(bytes) => bytes,
+            Err(_) => continue,
+        };
+        if is_binary(&bytes) {
+            continue;
+        }
+        let text = String::from_utf8_lossy(&bytes);
+        let lines: Vec<&str> = text.lines().collect();
+        let matching: Vec<usize> = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| regex.is_match(line))
+            .map(|(i, _)| i)
+            .collect();
+        if matching.is_empty() {
+            continue;
+        }
+        let name = relative.display().to_string();
+        counts.push((name.clone(), matching.len()));
+
+        let mut last_printed: Option<usize> = None;
+        for &line_index in &matching {
+            if total >= options.max_results {
+                break;
+            }
+            total += 1;
+            let start = line_index.saturating_sub(options.context);
+            let end = (line_index + options.context).min(lines.len() - 1);
+            let from = match last_printed {
+                Some(last) if last + 1 >= start => last + 1,
+                Some(_) => {
+                    output.push("--".to_string());
+                    start
+                }
+                None => {
+                    if !output.is_empty() {
+                        output.push("--".to_string());
+                    }
+                    start
+                }
+            };
+            for (i, line) in lines.iter().enumerate().take(end + 1).skip(from) {
+                let separator = if matching.binary_search(&i).is_ok() {
+                    ':'
+                } else {
+                    '-'
+                };
+                output.push(format!(
+                    "{}{}{}{}{}",
+                    name,
+                    separator,
+                    i + 1,
+                    separator,
+                    line
+                ));
+            }
+            last_printed = Some(end.max(last_printed.unwrap_or(0)));
+        }
+    }
+
+    let skipped_note = if skipped.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "\n[Skipped {} files over {}: {}]",
+            skipped.len(),
+            format_size(MAX_SEARCH_FILE_BYTES),
+            skipped.join(", ")
+        )
+    };
+    if counts.is_empty() {
+        return Ok(format!(
+            "No matches found for '{}'{}",
+            pattern, skipped_note
+        ));
+    }
+    let match_count: usize = counts.iter().map(|(_, c)| c).sum();
+    let mut result = output.join("\n");
+    if match_count > total {
+        result.push_str(&format!(
+            "\n[Showing the first {} of {} matching lines; narrow the pattern or path, or raise max_results]",
+            total, match_count
+        ));
+    }
+    result.push_str(&format!(
+        "\n[{} matching lines in {} files]\n",
+        match_count,
+        counts.len()
+    ));
+    result.push_str(
+        &counts
+            .iter()
+            .map(|(name, count)| format!("{}: {}", name, count))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    );
+    result.push_str(&skipped_note);
+    Ok(result)
+}
+
+pub fn find_files(
+    root: &Path,
+    pattern: &str,
+    sort: FindSort,
+    max_results: usize,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if pattern.is_empty() {
+        return Err("pattern must not be empty".into());
+    }
+    let matcher = glob_matcher(pattern)?;
+    let mut found = Vec::new();
+    for result in walker(root, None) {
+        let entry = match result {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(root)?.to_path_buf();
+        if !glob_matches(&matcher, pattern, &relative) {
+            continue;
+        }
+        let modified = entry
+            .metadata()
+            .ok()
+            .and_then(|m| m.modified().ok())
+            .unwrap_or(std::time::UNIX_EPOCH);
+        found.push((relative, modified));
+    }
+    if found.is_empty() {
+        return Ok(format!("No files found matching '{}'", pattern));
+    }
+    if sort == FindSort::Modified {
+        // Most recently modified first; the walk already sorted by name.
+        found.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+    }
+
+    let total = found.len();
+    let mut result = found
+        .iter()
+        .take(max_results)
+        .map(|(path, _)| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    if total > max_results {
+        result.push_str(&format!(
+            "\n[Showing {} of {} files; use a more specific pattern or path, or raise max_results]",
+            max_results, total
+        ));
+    } else {
+        result.push_str(&format!("\n[{} files found]", total));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn sample_tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src/nested")).unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        fs::write(root.join("Cargo.toml"), "[package]\n").unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(root.join("src/nested/lib.rs"), "").unwrap();
+        fs::write(root.join("target/debug/app"), "binary").unwrap();
+        dir
+    }
+
+    fn options() -> ListOptions {
+        ListOptions {
+            depth: 3,
+            tree: false,
+            glob: None,
+            sizes: false,
+            max_entries: 100,
+        }
+    }
+
+    #[test]
+    fn test_list_files_respects_gitignore() {
+        let dir = sample_tree();
+        let output = list_files(dir.path(), &options()).unwrap();
+        assert_eq!(
+            output,
+            "Cargo.toml\nsrc/\nsrc/main.rs\nsrc/nested/\nsrc/nested/lib.rs"
+        );
+    }
+
+    #[test]
+    fn test_list_files_tree_with_depth_and_sizes() {
+        let dir = sample_tree();
+        let opts = ListOptions {
+            depth: 2,
+            tree: true,
+            sizes: true,
+            ..options()
+        };
+        let output = list_files(dir.path(), &opts).unwrap();
+        assert_eq!(
+            output,
+            "├── Cargo.toml (10 B)\n└── src/\n    ├── main.rs (13 B)\n    └── nested/"
+        );
+    }
+
+    #[test]
+    fn test_list_files_glob_and_cap() {
+        let dir = sample_tree();
+        let opts = ListOptions {
+            glob: Some("*.rs".to_string()),
+            max_entries: 2,
+            ..options()
+        };
+        let output = list_files(dir.path(), &opts).unwrap();
+        assert_eq!(
+            output,
+            "src/\nsrc/main.rs\n[... 2 more entries not shown; narrow the path, lower depth or use glob]"
+        );
+    }
+
+    fn search_options() -> SearchOptions {
+        SearchOptions {
+            literal: false,
+            case_insensitive: false,
+            include: None,
+            exclude: None,
+            context: 0,
+            max_results: 100,
+        }
+    }
+
+    #[test]
+    fn test_search_files_regex_with_counts() {
+        let dir = sample_tree();
+        fs::write(dir.path().join("src/util.rs"), "fn a() {}\nfn b() {}\n")
+            .unwrap();
+        let output =
+            search_files(dir.path(), r"fn \w+\(", &search_options()).unwrap();
+        assert_eq!(
+            output,
+            "src/main.rs:1:fn main() {}\n--\nsrc/util.rs:1:fn a() {}\nsrc/util.rs:2:fn b() {}\n[3 matching lines in 2 files]\nsrc/main.rs: 1\nsrc/util.rs: 2"
+        );
+    }
+
+    #[test]
+    fn test_search_files_literal_context_and_globs() {
+        let dir = sample_tree();
+        fs::write(
+            dir.path().join("src/notes.txt"),
+            "one\ntwo (x)\nthree\nfour\nfive (x)\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("src/other.md"), "(x)\n").unwrap();
+        let opts = SearchOptions {
+            literal: true,
+            include: Some("*.txt".to_string()),
+            context: 1,
+            max_results: 1,
+            ..search_options()
+        };
+        let output = search_files(dir.path(), "(x)", &opts).unwrap();
+        assert_eq!(
+            output,
+            "src/notes.txt-1-one\nsrc/notes.txt:2:two (x)\nsrc/notes.txt-3-three\n[Showing the first 1 of 2 matching lines; narrow the pattern or path, or raise max_results]\n[2 matching lines in 1 files]\nsrc/notes.txt: 2"
+        );
+    }
+
+    #[test]
+    fn test_search_files_skips_ignored_and_binary_files() {
+        let dir = sample_tree();
+        fs::write(dir.path().join("blob.bin"), b"binary\0fn").unwrap();
+        let opts = SearchOptions {
+            exclude: Some("src/**".to_string()),
+            ..search_options()
+        };
+        let output = search_files(dir.path(), "binary|fn", &opts).unwrap();
+        assert_eq!(output, "No matches found for 'binary|fn'");
+    }
+
+    #[test]
+    fn test_search_files_skips_large_files() {
+        let dir = sample_tree();
+        let big = "fn big() {}\n".repeat(400_000);
+        fs::write(dir.path().join("big.log"), big).unwrap();
+        let output =
+            search_files(dir.path(), "fn big", &search_options()).unwrap();
+        assert_eq!(
+            output,
+            "No matches found for 'fn big'\n[Skipped 1 files over 4.0 MB: big.log]"
+        );
+    }
+
+    #[test]
+    fn test_find_files_glob_patterns() {
+        let dir = sample_tree();
+        let output =
+            find_files(dir.path(), "**/*.rs", FindSort::Name, 10).unwrap();
+        assert_eq!(output, "src/main.rs\nsrc/nested/lib.rs\n[2 files found]");
+        let output =
+            find_files(dir.path(), "Cargo.toml", FindSort::Name, 10).unwrap();
+        assert_eq!(output, "Cargo.toml\n[1 files found]");
+        let output =
+            find_files(dir.path(), "*.missing", FindSort::Name, 10).unwrap();
+        assert_eq!(output, "No files found matching '*.missing'");
+    }
+
+    #[test]
+    fn test_find_files_sorted_by_modification_time_and_capped() {
+        let dir = sample_tree();
+        let old =
+            std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+        fs::File::options()
+            .write(true)
+            .open(dir.path().join("src/main.rs"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        let output =
+            find_files(dir.path(), "*", FindSort::Modified, 2).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(!lines[..2].contains(&"src/main.rs"));
+        assert_eq!(
+            lines[2],
+            "[Showing 2 of 3 files; use a more specific pattern or path, or raise max_results]"
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Content hashes of files as the model last saw them, either through a read
+// tool or through one of its own writes.
+static SEEN: Mutex<Option<HashMap<PathBuf, u64>>> = Mutex::new(None);
+
+#[derive(Debug, PartialEq)]
+pub enum Freshness {
+    Fresh,
+    // The file does not exist, so there is nothing to clobber.
+    New,
+    Unread,
+    Changed,
+}
+
+fn key(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Remembers the current contents of `path` as seen by the model.
+pub fn record(path: &Path) {
+    if let Ok(bytes) = fs::read(path) {
+        let mut seen = SEEN.lock().unwrap();
+        seen.get_or_insert_with(HashMap::new).insert(key(path), hash(&bytes));
+    }
+}
+
+pub fn check(path: &Path) -> Freshness {
+    let Ok(bytes) = fs::read(path) else {
+        return Freshness::New;
+    };
+    let seen = SEEN.lock().unwrap();
+    match seen.as_ref().and_then(|seen| seen.get(&key(path))) {
+        None => Freshness::Unread,
+        Some(&known) if known == hash(&bytes) => Freshness::Fresh,
+        Some(_) => Freshness::Changed,
+    }
+}
+
+// Explains why writing to `path` blind would be risky, if it would be.
+pub fn stale_warning(path: &str) -> Option<String> {
+    match check(Path::new(path)) {
+        Freshness::Fresh | Freshness::New => None,
+        Freshness::Unread => Some(format!(
+            "`{}` has not been read in this session, so its current contents were not taken into account.",
+            path
+        )),
+        Freshness::Changed => Some(format!(
+            "`{}` has changed on disk since it was last read; writing now may overwrite those changes.",
+            path
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_tracks_reads_and_external_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        assert_eq!(check(&path), Freshness::New);
+        fs::write(&path, "first").unwrap();
+        assert_eq!(check(&path), Freshness::Unread);
+        record(&path);
+        assert_eq!(check(&path), Freshness::Fresh);
+        fs::write(&path, "changed by someone else").unwrap();
+        assert_eq!(check(&path), Freshness::Changed);
+        assert!(
+            stale_warning(path.to_str().unwrap())
+                .unwrap()
+                .contains("changed on disk")
+        );
+    }
+}
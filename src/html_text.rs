@@ -0,0 +1,410 @@
+// A small HTML to Markdown-ish text converter for web pages fetched by
+// http_request. It is deliberately forgiving: unknown tags are dropped and
+// only their text is kept.
+
+const SKIPPED: [&str; 6] =
+    ["script", "style", "noscript", "svg", "template", "iframe"];
+const BLOCKS: [&str; 24] = [
+    "p",
+    "div",
+    "section",
+    "article",
+    "header",
+    "footer",
+    "nav",
+    "main",
+    "aside",
+    "ul",
+    "ol",
+    "table",
+    "tr",
+    "blockquote",
+    "form",
+    "title",
+    "dl",
+    "dt",
+    "dd",
+    "figure",
+    "figcaption",
+    "details",
+    "summary",
+    "body",
+];
+
+struct Tag {
+    name: String,
+    closing: bool,
+    attrs: Vec<(String, String)>,
+}
+
+impl Tag {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn parse_tag(raw: &str) -> Tag {
+    let raw = raw.trim().trim_end_matches('/');
+    let (closing, raw) = match raw.strip_prefix('/') {
+        Some(rest) => (true, rest),
+        None => (false, raw),
+    };
+    let name_end = raw.find(|c: char| c.is_whitespace()).unwrap_or(raw.len());
+    let name = raw[..name_end].to_lowercase();
+    let mut attrs = Vec::new();
+    let mut rest = raw[name_end..].trim_start();
+    while !rest.is_empty() {
+        let key_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let key = rest[..key_end].to_lowercase();
+        rest = rest[key_end..].trim_start();
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (parsed, remaining) = match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let body = &after[1..];
+                    let end = body.find(quote).unwrap_or(body.len());
+                    (&body[..end], body.get(end + 1..).unwrap_or(""))
+                }
+                _ => {
+                    let end = after
+                        .find(|c: char| c.is_whitespace())
+                        .unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            value = decode_entities(parsed);
+            rest = remaining.trim_start();
+        }
+        if key.is_empty() {
+            break;
+        }
+        attrs.push((key, value));
+    }
+    Tag {
+        name,
+        closing,
+        attrs,
+    }
+}
+
+pub fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(';').filter(|&end| end <= 10);
+        let decoded = end.and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => ' ',
+                "mdash" => '—',
+                "ndash" => '–',
+                "hellip" => '…',
+                "copy" => '©',
+                "rsquo" => '’',
+                "lsquo" => '‘',
+                "rdquo" => '”',
+                "ldquo" => '“',
+                _ => {
+                    let code = match entity.strip_prefix('#') {
+                        Some(hex) if hex.starts_with(['x', 'X']) => {
+                            u32::from_str_radix(&hex[1..], 16).ok()
+                        }
+                        Some(dec) => dec.parse().ok(),
+                        None => None,
+                    };
+                    code.and_then(char::from_u32)?
+                }
+            };
+            Some((c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+struct Writer {
+    out: String,
+    in_pre: bool,
+    lists: Vec<Option<usize>>,
+    // Start of the text of the open link and its target.
+    links: Vec<(usize, String)>,
+}
+
+impl Writer {
+    fn at_line_start(&self) -> bool {
+        self.out.is_empty() || self.out.ends_with('\n')
+    }
+
+    fn text(&mut self, raw: &str) {
+        let text = decode_entities(raw);
+        if self.in_pre {
+            self.out.push_str(&text);
+            return;
+        }
+        for (i, word) in text.split_whitespace().enumerate() {
+            let starts_with_space =
+                i > 0 || text.starts_with(|c: char| c.is_whitespace());
+            if starts_with_space
+                && !self.at_line_start()
+                && !self.out.ends_with(' ')
+            {
+                self.out.push(' ');
+            }
+            self.out.push_str(word);
+        }
+        if text.ends_with(|c: char| c.is_whitespace())
+            && !text.trim().is_empty()
+        {
+            self.out.push(' ');
+        }
+    }
+
+    // Ends the current line and adds blank lines up to `lines` newlines.
+    fn line_break(&mut self, lines: usize) {
+        while self.out.ends_with(' ') {
+            self.out.pop();
+        }
+        // Open links may have started inside the trimmed spaces.
+        for (start, _) in &mut self.links {
+            *start = (*start).min(self.out.len());
+        }
+        if self.out.is_empty() {
+            return;
+        }
+        let existing = self.out.len() - self.out.trim_end_matches('\n').len();
+        for _ in existing..lines {
+            self.out.push('\n');
+        }
+    }
+
+    fn open(&mut self, tag: &Tag) {
+        match tag.name.as_str() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.line_break(2);
+                let level = tag.name[1..].parse().unwrap_or(1);
+                self.out.push_str(&"#".repeat(level));
+                self.out.push(' ');
+            }
+            "br" => self.out.push('\n'),
+            "hr" => {
+                self.line_break(2);
+                self.out.push_str("---");
+                self.line_break(2);
+            }
+            "pre" => {
+                self.line_break(2);
+                self.out.push_str("```\n");
+                self.in_pre = true;
+            }
+            "code" if !self.in_pre => self.out.push('`'),
+            "strong" | "b" => self.out.push_str("**"),
+            "ul" => {
+                self.line_break(1);
+                self.lists.push(None);
+            }
+            "ol" => {
+                self.line_break(1);
+                self.lists.push(Some(0));
+            }
+            "li" => {
+                self.line_break(1);
+                let depth = self.lists.len().max(1);
+                self.out.push_str(&"  ".repeat(depth - 1));
+                match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        self.out.push_str(&format!("{}. ", n));
+                    }
+                    _ => self.out.push_str("- "),
+                }
+            }
+            "td" | "th" if !self.at_line_start() => self.out.push_str(" | "),
+            "a" => {
+                let href = tag.attr("href").unwrap_or("").to_string();
+                self.links.push((self.out.len(), href));
+            }
+            "img" => {
+                if let Some(alt) = tag.attr("alt").filter(|a| !a.is_empty()) {
+                    self.out.push_str(&format!("[image: {}]", alt));
+                }
+            }
+            name if BLOCKS.contains(&name) => self.line_break(2),
+            _ => {}
+        }
+    }
+
+    fn close(&mut self, tag: &Tag) {
+        match tag.name.as_str() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => self.line_break(2),
+            "pre" => {
+                self.in_pre = false;
+                self.line_break(1);
+                self.out.push_str("```");
+                self.line_break(2);
+            }
+            "code" if !self.in_pre => self.out.push('`'),
+            "strong" | "b" => self.out.push_str("**"),
+            "ul" | "ol" => {
+                self.lists.pop();
+                self.line_break(2);
+            }
+            "li" => self.line_break(1),
+            "a" => {
+                let Some((start, href)) = self.links.pop() else {
+                    return;
+                };
+                let usable = !href.is_empty()
+                    && !href.starts_with('#')
+                    && !href.starts_with("javascript:");
+                let text = self.out[start..].trim();
+                if usable && !text.is_empty() {
+                    let text = text.to_string();
+                    self.out.truncate(start);
+                    self.out.push_str(&format!("[{}]({})", text, href));
+                }
+            }
+            name if BLOCKS.contains(&name) => self.line_break(2),
+            _ => {}
+        }
+    }
+}
+
+pub fn looks_like_html(content_type: &str, body: &str) -> bool {
+    if content_type.contains("html") {
+        return true;
+    }
+    let start = body.trim_start().get(..15).unwrap_or("").to_lowercase();
+    start.starts_with("<!doctype html") || start.starts_with("<html")
+}
+
+pub fn html_to_text(html: &str) -> String {
+    let mut writer = Writer {
+        out: String::new(),
+        in_pre: false,
+        lists: Vec::new(),
+        links: Vec::new(),
+    };
+    let mut skipping: Option<String> = None;
+    let mut rest = html;
+    while !rest.is_empty() {
+        let Some(start) = rest.find('<') else {
+            if skipping.is_none() {
+                writer.text(rest);
+            }
+            break;
+        };
+        if skipping.is_none() {
+            writer.text(&rest[..start]);
+        }
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            if skipping.is_none() {
+                writer.text(rest);
+            }
+            break;
+        };
+        let raw = &rest[1..end];
+        rest = &rest[end + 1..];
+        if raw.starts_with('!') || raw.starts_with('?') {
+            continue;
+        }
+        let tag = parse_tag(raw);
+        if let Some(skipped) = &skipping {
+            if tag.closing && &tag.name == skipped {
+                skipping = None;
+            }
+            continue;
+        }
+        if tag.closing {
+            writer.close(&tag);
+        } else if SKIPPED.contains(&tag.name.as_str()) {
+            skipping = Some(tag.name);
+        } else {
+            writer.open(&tag);
+        }
+    }
+    let mut text = String::new();
+    let mut blank_lines = 0;
+    for line in writer.out.lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank_lines += 1;
+            if blank_lines > 1 || text.is_empty() {
+                continue;
+            }
+        } else {
+            blank_lines = 0;
+        }
+        text.push_str(line);
+        text.push('\n');
+    }
+    text.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_text_converts_structure() {
+        let html = r#"<!DOCTYPE html>
+<html><head><title>Docs</title><style>body { color: red }</style>
+<script>var x = "<p>not text</p>";</script></head>
+<body>
+  <h1>Getting   started</h1>
+  <p>Install with <code>cargo install</code> &amp; read the
+     <a href="https://example.com/guide">guide</a>.</p>
+  <ul><li>One</li><li>Two <b>bold</b></li></ul>
+  <ol><li>First</li><li>Second</li></ol>
+  <pre>fn main() {
+    println!("hi");
+}</pre>
+  <!-- a comment -->
+  <p>Tom &lt;3 Jerry&#33;<br>Next line</p>
+</body></html>"#;
+        assert_eq!(
+            html_to_text(html),
+            "Docs\n\n# Getting started\n\nInstall with `cargo install` & read the [guide](https://example.com/guide).\n\n- One\n- Two **bold**\n\n1. First\n2. Second\n\n```\nfn main() {\n    println!(\"hi\");\n}\n```\n\nTom <3 Jerry!\nNext line"
+        );
+    }
+
+    #[test]
+    fn test_html_to_text_link_around_trimmed_spaces() {
+        let text = html_to_text(r#"</b><pre>  <a href="x"><ul><code></a>"#);
+        assert!(text.starts_with("**"));
+    }
+
+    #[test]
+    fn test_looks_like_html() {
+        assert!(looks_like_html("text/html; charset=utf-8", ""));
+        assert!(looks_like_html("", "  <!DOCTYPE html><html>"));
+        assert!(!looks_like_html("application/json", "{\"a\": 1}"));
+    }
+}
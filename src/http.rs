@@ -0,0 +1,316 @@
+use reqwest::redirect;
+use reqwest::{Method, Url};
+use std::time::Duration;
+
+use crate::html_text;
+
+const MAX_REDIRECTS: usize = 10;
+
+#[derive(Clone)]
+pub struct HttpOptions {
+    // When non-empty, only these domains (and their subdomains) are allowed.
+    pub allow_domains: Vec<String>,
+    pub deny_domains: Vec<String>,
+    pub max_response_bytes: usize,
+    pub timeout: Duration,
+    // Plan mode only allows requests that cannot change anything.
+    pub read_only: bool,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        HttpOptions {
+            allow_domains: Vec::new(),
+            deny_domains: Vec::new(),
+            max_response_bytes: 100_000,
+            timeout: Duration::from_secs(30),
+            read_only: false,
+        }
+    }
+}
+
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    pub timeout: Option<Duration>,
+}
+
+pub struct HttpResponse {
+    pub status: String,
+    pub headers: Vec<(String, String)>,
+    pub content_type: String,
+    pub body: String,
+    pub truncated: bool,
+}
+
+fn domain_matches(host: &str, domain: &str) -> bool {
+    let domain = domain.trim_start_matches("*.").to_lowercase();
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+pub fn check_domain(url: &Url, options: &HttpOptions) -> Result<(), String> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| format!("{} has no host", url))?
+        .to_lowercase();
+    if options.deny_domains.iter().any(|d| domain_matches(&host, d)) {
+        return Err(format!("requests to {} are denied by config", host));
+    }
+    if !options.allow_domains.is_empty()
+        && !options.allow_domains.iter().any(|d| domain_matches(&host, d))
+    {
+        return Err(format!(
+            "{} is not in the allowed domains ({})",
+            host,
+            options.allow_domains.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+pub fn is_read_only(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD)
+}
+
+pub async fn send(
+    request: &HttpRequest,
+    options: &HttpOptions,
+) -> Result<HttpResponse, String> {
+    let url = Url::parse(&request.url)
+        .map_err(|e| format!("invalid URL {}: {}", request.url, e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("unsupported URL scheme {}", url.scheme()));
+    }
+    check_domain(&url, options)?;
+    // Redirects must not lead outside the allowed domains either.
+    let redirect_options = options.clone();
+    let policy = redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if let Err(e) = check_domain(attempt.url(), &redirect_options) {
+            attempt.error(e)
+        } else {
+            attempt.follow()
+        }
+    });
+    let client = reqwest::Client::builder()
+        .redirect(policy)
+        .timeout(request.timeout.unwrap_or(options.timeout))
+        .user_agent(concat!("attotool/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut builder = client.request(request.method.clone(), url);
+    for (name, value) in &request.headers {
+        builder = builder.header(name, value);
+    }
+    if let Some(body) = &request.body {
+        builder = builder.body(body.clone());
+    }
+    let mut response = builder.send().await.map_err(|e| e.to_string())?;
+    let status = response.status().to_string();
+    let headers: Vec<(String, String)> = response
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect();
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    let mut bytes = Vec::new();
+    let mut truncated = false;
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        let room = options.max_response_bytes - bytes.len();
+        if chunk.len() > room {
+            bytes.extend_from_slice(&chunk[..room]);
+            truncated = true;
+            break;
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(HttpResponse {
+        status,
+        headers,
+        content_type,
+        body: String::from_utf8_lossy(&bytes).into_owned(),
+        truncated,
+    })
+}
+
+pub fn format_response(
+    response: &HttpResponse,
+    method: &Method,
+    raw: bool,
+) -> String {
+    let mut output = format!("HTTP {}\n", response.status);
+    for (name, value) in &response.headers {
+        let interesting = matches!(
+            name.as_str(),
+            "content-type" | "content-length" | "location"
+        );
+        if *method == Method::HEAD || interesting {
+            output.push_str(&format!("{}: {}\n", name, value));
+        }
+    }
+    if *method == Method::HEAD {
+        return output.trim_end().to_string();
+    }
+    output.push('\n');
+    if !raw
+        && html_text::looks_like_html(&response.content_type, &response.body)
+    {
+        output.push_str(&html_text::html_to_text(&response.body));
+    } else {
+        output.push_str(&response.body);
+    }
+    if response.truncated {
+        output.push_str(
+            "\n[Response truncated: the body exceeded the size limit]",
+        );
+    }
+    output
+}
+
+#[cfg(test)]
+pub mod test_server {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    // Serves `response` (a complete raw HTTP response) to one connection and
+    // yields the raw request it received.
+    pub async fn serve_once(response: String) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let length = text
+                        .lines()
+                        .find_map(|l| {
+                            l.to_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|v| v.trim().parse().unwrap_or(0))
+                        })
+                        .unwrap_or(0);
+                    if request.len() >= end + 4 + length {
+                        break;
+                    }
+                }
+                if n == 0 {
+                    break;
+                }
+            }
+            socket.write_all(response.as_bytes()).await.unwrap();
+            socket.shutdown().await.ok();
+            String::from_utf8_lossy(&request).to_string()
+        });
+        (url, handle)
+    }
+
+    pub fn response(status: &str, content_type: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+            status,
+            content_type,
+            body.len(),
+            body
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_server::{response, serve_once};
+    use super::*;
+
+    fn get(url: &str) -> HttpRequest {
+        HttpRequest {
+            method: Method::GET,
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: None,
+            timeout: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_converts_html_and_caps_size() {
+        let html = "<html><body><h1>Hi</h1><p>there</p></body></html>";
+        let (url, server) =
+            serve_once(response("200 OK", "text/html", html)).await;
+        let result = send(&get(&url), &HttpOptions::default()).await.unwrap();
+        server.await.unwrap();
+        let output = format_response(&result, &Method::GET, false);
+        assert_eq!(
+            output,
+            "HTTP 200 OK\ncontent-type: text/html\ncontent-length: 49\n\n# Hi\n\nthere"
+        );
+
+        let (url, server) =
+            serve_once(response("200 OK", "text/plain", &"x".repeat(50))).await;
+        let options = HttpOptions {
+            max_response_bytes: 10,
+            ..Default::default()
+        };
+        let result = send(&get(&url), &options).await.unwrap();
+        server.await.unwrap();
+        assert_eq!(result.body, "x".repeat(10));
+        assert!(result.truncated);
+    }
+
+    #[tokio::test]
+    async fn test_send_passes_method_headers_and_body() {
+        let (url, server) =
+            serve_once(response("201 Created", "application/json", "{}")).await;
+        let request = HttpRequest {
+            method: Method::POST,
+            url: format!("{}/items", url),
+            headers: vec![("X-Token".to_string(), "secret".to_string())],
+            body: Some("{\"name\":\"a\"}".to_string()),
+            timeout: None,
+        };
+        let result = send(&request, &HttpOptions::default()).await.unwrap();
+        let received = server.await.unwrap();
+        assert_eq!(result.status, "201 Created");
+        assert!(received.starts_with("POST /items HTTP/1.1"));
+        assert!(received.to_lowercase().contains("x-token: secret"));
+        assert!(received.ends_with("{\"name\":\"a\"}"));
+    }
+
+    #[test]
+    fn test_check_domain_allow_and_deny_lists() {
+        let options = HttpOptions {
+            allow_domains: vec!["example.com".to_string()],
+            deny_domains: vec!["private.example.com".to_string()],
+            ..Default::default()
+        };
+        let url = |u: &str| Url::parse(u).unwrap();
+        assert!(check_domain(&url("https://example.com/a"), &options).is_ok());
+        assert!(
+            check_domain(&url("https://docs.example.com"), &options).is_ok()
+        );
+        assert!(
+            check_domain(&url("https://private.example.com"), &options)
+                .is_err()
+        );
+        assert!(
+            check_domain(&url("https://notexample.com"), &options).is_err()
+        );
+    }
+}
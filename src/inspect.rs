@@ -0,0 +1,116 @@
+use serde_json::{Value, json};
+
+use crate::attotool::RequestPrompt;
+use crate::tools::Tool;
+use crate::yaml_utilities::join_sections;
+
+// A rough estimate; the exact count depends on the model's tokenizer.
+fn approx_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+fn pretty(value: &Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_default()
+}
+
+// The system prompt and response format of `prompt`, with the approximate
+// token count of each section.
+pub fn prompt_report(prompt: &RequestPrompt, as_json: bool) -> String {
+    let system = join_sections(&prompt.sections);
+    let response_format =
+        serde_json::to_value(&prompt.response_format).unwrap_or(Value::Null);
+    if as_json {
+        let sections: Vec<Value> = prompt
+            .sections
+            .iter()
+            .map(|(name, text)| {
+                json!({"name": name, "text": text, "tokens": approx_tokens(text)})
+            })
+            .collect();
+        return pretty(&json!({
+            "system_prompt": system,
+            "tokens": approx_tokens(&system),
+            "sections": sections,
+            "response_format": response_format,
+        }));
+    }
+    let mut report =
+        format!("{}\n\n--- Sections (approximate tokens)\n", system);
+    for (name, text) in &prompt.sections {
+        report.push_str(&format!("{:>6}  {}\n", approx_tokens(text), name));
+    }
+    report.push_str(&format!("{:>6}  total\n", approx_tokens(&system)));
+    report.push_str("\n--- Response format\n");
+    match response_format {
+        Value::Null => report.push_str("none (free-form text)"),
+        value => report.push_str(&pretty(&value)),
+    }
+    report
+}
+
+pub fn tools_report(tools: &[Tool], as_json: bool) -> String {
+    if as_json {
+        let tools: Vec<Value> = tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "name": tool.name(),
+                    "description": tool.description(),
+                    "input_schema": tool.args_schema(),
+                })
+            })
+            .collect();
+        return pretty(&Value::Array(tools));
+    }
+    tools
+        .iter()
+        .map(|tool| {
+            format!("{}\n  schema: {}", tool.format(), tool.args_schema())
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::{ToolConfig, get_tools};
+
+    #[test]
+    fn test_prompt_and_tools_reports() {
+        let prompt = RequestPrompt {
+            tools: Vec::new(),
+            sections: vec![
+                ("identity".to_string(), "You are an agent.".to_string()),
+                ("tools".to_string(), "Tools: none".to_string()),
+            ],
+            response_format: None,
+        };
+        assert_eq!(
+            prompt_report(&prompt, false),
+            "You are an agent.\n\nTools: none\n\n--- Sections (approximate tokens)\n     5  identity\n     3  tools\n     8  total\n\n--- Response format\nnone (free-form text)"
+        );
+        let report: Value =
+            serde_json::from_str(&prompt_report(&prompt, true)).unwrap();
+        assert_eq!(report["tokens"], 8);
+        assert_eq!(report["sections"][1]["name"], "tools");
+        assert_eq!(report["response_format"], Value::Null);
+
+        let tools = get_tools(false, true, true, true, &ToolConfig::default());
+        let report: Value =
+            serde_json::from_str(&tools_report(&tools, true)).unwrap();
+        let names: Vec<&str> = report
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["name"].as_str().unwrap())
+            .collect();
+        assert!(names.contains(&"read_file"));
+        assert!(!names.contains(&"write_file"));
+        assert!(!names.contains(&"execute_shell_command"));
+        let read_file =
+            &report[names.iter().position(|n| *n == "read_file").unwrap()];
+        assert_eq!(read_file["input_schema"]["required"][0], "path");
+        assert!(tools_report(&tools, false).contains("read_file: '"));
+    }
+}
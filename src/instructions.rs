@@ -0,0 +1,227 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::settings::InstructionsConfig;
+use crate::trust::project_root;
+
+// Directories from `root` down to `dir`, outermost first. Empty when `dir`
+// is outside `root`.
+fn dirs_between(root: &Path, dir: &Path) -> Vec<PathBuf> {
+    if !dir.starts_with(root) {
+        return Vec::new();
+    }
+    let mut dirs: Vec<PathBuf> = dir
+        .ancestors()
+        .take_while(|d| d.starts_with(root))
+        .map(|d| d.to_path_buf())
+        .collect();
+    dirs.reverse();
+    dirs
+}
+
+// Instruction files named `names` in the directories from the repository
+// root down to `dir`, outermost first.
+pub fn files_up_to(dir: &Path, names: &[String]) -> Vec<PathBuf> {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    dirs_between(&project_root(&dir), &dir)
+        .iter()
+        .flat_map(|d| names.iter().map(move |name| d.join(name)))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+// The sections of a markdown document whose heading contains one of
+// `wanted`, each running until the next heading of the same or a higher
+// level.
+fn markdown_sections(content: &str, wanted: &[String]) -> String {
+    let wanted: Vec<String> = wanted.iter().map(|w| w.to_lowercase()).collect();
+    let mut sections = Vec::new();
+    let mut current: Option<(usize, Vec<&str>)> = None;
+    let mut in_code = false;
+    for line in content.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+        }
+        let level = line.chars().take_while(|c| *c == '#').count();
+        let heading = !in_code
+            && (1..=6).contains(&level)
+            && line[level..].starts_with(' ');
+        if heading {
+            if let Some((open, lines)) = current.take() {
+                if level > open {
+                    current = Some((open, lines));
+                } else {
+                    sections.push(lines.join("\n"));
+                }
+            }
+            if current.is_none() {
+                let title = line[level..].trim().to_lowercase();
+                if wanted.iter().any(|w| title.contains(w.as_str())) {
+                    current = Some((level, Vec::new()));
+                }
+            }
+        }
+        if let Some((_, lines)) = current.as_mut() {
+            lines.push(line);
+        }
+    }
+    if let Some((_, lines)) = current {
+        sections.push(lines.join("\n"));
+    }
+    sections.iter().map(|s| s.trim_end()).collect::<Vec<_>>().join("\n\n")
+}
+
+// Collects instruction files (AGENTS.md and the configured equivalents) for
+// the conversation: those above the working directory up front, and those
+// of a subdirectory the first time a file under it is used.
+pub struct Instructions {
+    current_dir: PathBuf,
+    root: PathBuf,
+    config: InstructionsConfig,
+    loaded: HashSet<PathBuf>,
+}
+
+impl Instructions {
+    pub fn new(current_dir: &Path, config: &InstructionsConfig) -> Self {
+        let current_dir = current_dir
+            .canonicalize()
+            .unwrap_or_else(|_| current_dir.to_path_buf());
+        Instructions {
+            root: project_root(&current_dir),
+            current_dir,
+            config: config.clone(),
+            loaded: HashSet::new(),
+        }
+    }
+
+    fn display_path(&self, path: &Path) -> String {
+        path.strip_prefix(&self.current_dir)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    }
+
+    fn read(&self, path: &Path) -> Option<String> {
+        let content = fs::read_to_string(path).ok()?;
+        let is_contributing = path
+            .file_name()
+            .is_some_and(|n| n.eq_ignore_ascii_case("CONTRIBUTING.md"));
+        let content = if is_contributing {
+            markdown_sections(&content, &self.config.contributing_sections)
+        } else {
+            content
+        };
+        if content.trim().is_empty() {
+            return None;
+        }
+        Some(format!(
+            "[read_file path: '{}']\n{}",
+            self.display_path(path),
+            content
+        ))
+    }
+
+    // Instructions of the directories from the root down to `dir` that were
+    // not loaded yet, outermost first.
+    fn load_dirs(&mut self, dir: &Path) -> Option<String> {
+        let mut parts = Vec::new();
+        for d in dirs_between(&self.root, dir) {
+            if !self.loaded.insert(d.clone()) {
+                continue;
+            }
+            for name in &self.config.files {
+                let path = d.join(name);
+                if let Some(part) = self.read(&path) {
+                    parts.push(part);
+                }
+            }
+        }
+        match parts.len() {
+            0 => None,
+            1 => parts.pop(),
+            _ => Some(format!(
+                "Project instructions, outermost directory first; where they disagree, the later (nearer) file takes precedence.\n\n{}",
+                parts.join("\n\n")
+            )),
+        }
+    }
+
+    // Instructions from the repository root down to the working directory.
+    pub fn initial(&mut self) -> Option<String> {
+        let dir = self.current_dir.clone();
+        self.load_dirs(&dir)
+    }
+
+    // Instructions of the directories leading to `path` that were not
+    // loaded yet; `path` may be relative to the working directory.
+    pub fn for_path(&mut self, path: &str) -> Option<String> {
+        let path = self.current_dir.join(path);
+        let dir = if path.is_dir() {
+            path
+        } else {
+            path.parent()?.to_path_buf()
+        };
+        let dir = dir.canonicalize().ok()?;
+        self.load_dirs(&dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hierarchical_and_lazy_instructions() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap().join("repo");
+        let app = root.join("app");
+        let core = root.join("crates/core");
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(&app).unwrap();
+        fs::create_dir_all(core.join("src")).unwrap();
+        fs::write(root.join("AGENTS.md"), "Use rustfmt.").unwrap();
+        fs::write(app.join(".cursorrules"), "Prefer small functions.").unwrap();
+        fs::write(core.join("AGENTS.md"), "No unsafe.").unwrap();
+        fs::write(core.join("src/lib.rs"), "").unwrap();
+        fs::write(
+            root.join("CONTRIBUTING.md"),
+            "# Contributing\n\n## License\n\nSign the CLA.\n\n## Code style\n\nRun clippy.\n\n```\n# not a heading\n```\n\n### Details\n\nNo panics.\n\n## Releases\n\nTag it.\n",
+        )
+        .unwrap();
+
+        let config = InstructionsConfig {
+            files: vec![
+                "AGENTS.md".to_string(),
+                ".cursorrules".to_string(),
+                "CONTRIBUTING.md".to_string(),
+            ],
+            ..Default::default()
+        };
+        let mut instructions = Instructions::new(&app, &config);
+        assert_eq!(
+            instructions.initial().unwrap(),
+            format!(
+                "Project instructions, outermost directory first; where they disagree, the later (nearer) file takes precedence.\n\n[read_file path: '{root}/AGENTS.md']\nUse rustfmt.\n\n[read_file path: '{root}/CONTRIBUTING.md']\n## Code style\n\nRun clippy.\n\n```\n# not a heading\n```\n\n### Details\n\nNo panics.\n\n[read_file path: '.cursorrules']\nPrefer small functions.",
+                root = root.display()
+            )
+        );
+        assert_eq!(
+            instructions.for_path("../crates/core/src/lib.rs").unwrap(),
+            format!(
+                "[read_file path: '{}/crates/core/AGENTS.md']\nNo unsafe.",
+                root.display()
+            )
+        );
+        assert!(instructions.for_path("../crates/core/Cargo.toml").is_none());
+        assert!(instructions.for_path("/etc/hosts").is_none());
+        assert_eq!(
+            files_up_to(&app, &config.files),
+            vec![
+                root.join("AGENTS.md"),
+                root.join("CONTRIBUTING.md"),
+                app.join(".cursorrules"),
+            ]
+        );
+    }
+}
@@ -1,43 +1,72 @@
-use attotool::loop_tools_until_finish;
-use clap::Parser;
+use attotool::{RunOptions, loop_tools_until_finish, request_prompt};
+use clap::{Parser, Subcommand};
+use serde_yaml::{Mapping, Value as YamlValue};
+use std::env;
+use yaml_utilities::PromptFlags;
 
 mod attotool;
+mod chat_backend;
+mod command_tools;
+mod edits;
+mod file_read;
+mod file_search;
+mod file_versions;
+mod html_text;
+mod http;
+mod inspect;
+mod instructions;
+mod mcp;
+mod mcp_serve;
+mod patch;
+mod providers;
 mod response_formats;
+mod settings;
+mod shell;
 mod tools;
+mod transaction;
+mod trust;
+mod web_search;
 mod yaml_utilities;
 
 #[derive(Parser)]
 #[command(name = "attotool")]
 struct Args {
-    #[arg(long, help = "LLM model to use")]
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(long, global = true, help = "LLM model to use")]
     model: Option<String>,
-    #[arg(long, default_value_t = 2000, help = "Maximum tokens for response")]
-    max_tokens: u32,
+    #[arg(long, help = "Maximum tokens for response (default: 2000)")]
+    max_tokens: Option<u32>,
     #[arg(
         long,
-        default_value = "https://openrouter.ai/api/v1",
-        help = "OpenAI-compatible API base URL"
+        help = "Provider from config.yaml or built in: openrouter, openai, anthropic, ollama"
     )]
-    base_url: String,
+    provider: Option<String>,
+    #[arg(long, help = "API base URL, overriding the provider's")]
+    base_url: Option<String>,
+    #[arg(
+        long,
+        help = "API spoken by the endpoint, overriding the provider's: openai, anthropic, ollama"
+    )]
+    api: Option<String>,
     #[arg(long, help = "Task description")]
     input: Option<String>,
     #[arg(index = 1, conflicts_with = "input")]
     positional_input: Option<String>,
     #[arg(
         long,
-        default_value_t = 0,
-        help = "Maximum number of tool calls (0 for infinite)"
+        help = "Maximum number of tool calls (default: 0 for infinite)"
     )]
-    max_tool_calls: u32,
-    #[arg(long, default_value_t = 3, help = "Number of retries for API calls")]
-    retries: u32,
+    max_tool_calls: Option<u32>,
+    #[arg(long, help = "Number of retries for API calls (default: 3)")]
+    retries: Option<u32>,
     #[arg(long, help = "Enable very detailed output")]
     verbose: bool,
     #[arg(long, help = "Show tool execution details")]
     tool_call_details: bool,
-    #[arg(long, help = "Disable automatic AGENTS.md loading")]
+    #[arg(long, global = true, help = "Disable automatic AGENTS.md loading")]
     disable_agents_md: bool,
-    #[arg(long, help = "Skip approval prompts (YOLO mode)")]
+    #[arg(long, global = true, help = "Skip approval prompts (YOLO mode)")]
     yolo: bool,
     #[arg(
         short = 'c',
@@ -45,46 +74,305 @@ struct Args {
         help = "Reads the existing history.yaml and continues the conversation with a new user message"
     )]
     r#continue: bool,
-    #[arg(long, short = 'p', help = "Enable read-only planning mode")]
+    #[arg(
+        long,
+        short = 'p',
+        global = true,
+        help = "Enable read-only planning mode"
+    )]
     plan: bool,
-    #[arg(long, help = "Response format: yaml, json, json_fixed_key")]
+    #[arg(
+        long,
+        global = true,
+        help = "Response format: yaml, json, json_fixed_key"
+    )]
     format: Option<String>,
-    #[arg(long, help = "Disable the execute_shell_command tool")]
+    #[arg(
+        long,
+        global = true,
+        help = "Disable the execute_shell_command tool"
+    )]
     no_shell: bool,
-    #[arg(long, help = "Disable the ask_for_clarification tool")]
+    #[arg(
+        long,
+        global = true,
+        help = "Disable the ask_for_clarification tool"
+    )]
     no_clarify: bool,
+    #[arg(
+        long,
+        global = true,
+        help = "Run shell commands in a pseudo-terminal so they can prompt for input"
+    )]
+    pty: bool,
+    #[arg(long, help = "Don't stream shell command output to the terminal")]
+    no_stream: bool,
+    #[arg(
+        long,
+        global = true,
+        help = "Shell used to run command strings (bash, sh, zsh, fish)"
+    )]
+    shell: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        help = "Apply a named profile from the config files"
+    )]
+    profile: Option<String>,
+}
+
+impl Args {
+    // The settings given on the command line, as the topmost config layer.
+    // Flags that were not passed are left out so lower layers apply.
+    fn settings(&self) -> Mapping {
+        let mut layer = Mapping::new();
+        let strings = [
+            ("model", &self.model),
+            ("provider", &self.provider),
+            ("base_url", &self.base_url),
+            ("api", &self.api),
+            ("format", &self.format),
+            ("shell", &self.shell),
+        ];
+        for (key, value) in strings {
+            if let Some(value) = value {
+                layer.insert(key.into(), value.as_str().into());
+            }
+        }
+        let numbers = [
+            ("max_tokens", self.max_tokens),
+            ("max_tool_calls", self.max_tool_calls),
+            ("retries", self.retries),
+        ];
+        for (key, value) in numbers {
+            if let Some(value) = value {
+                layer.insert(key.into(), YamlValue::Number(value.into()));
+            }
+        }
+        let flags = [
+            ("verbose", self.verbose),
+            ("tool_call_details", self.tool_call_details),
+            ("disable_agents_md", self.disable_agents_md),
+            ("yolo", self.yolo),
+            ("plan", self.plan),
+            ("no_shell", self.no_shell),
+            ("no_clarify", self.no_clarify),
+            ("pty", self.pty),
+            ("no_stream", self.no_stream),
+        ];
+        for (key, value) in flags {
+            if value {
+                layer.insert(key.into(), true.into());
+            }
+        }
+        layer
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(
+        about = "Serve attotool's tools to MCP clients over stdio; approvals are asked on the terminal"
+    )]
+    McpServe,
+    #[command(about = "Inspect the configuration")]
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    #[command(
+        about = "Trust the current project so its AGENTS.md, .attotool.yaml and .env are used"
+    )]
+    Trust {
+        #[arg(long, help = "Stop trusting the current project")]
+        revoke: bool,
+    },
+    #[command(
+        about = "Print the system prompt and response format sent for the given flags, with approximate token counts"
+    )]
+    Prompt {
+        #[arg(long, help = "Print as JSON")]
+        json: bool,
+        #[arg(
+            long,
+            help = "Start the configured MCP servers to include their tools"
+        )]
+        mcp: bool,
+    },
+    #[command(
+        about = "List the tools offered to the model for the given flags"
+    )]
+    Tools {
+        #[arg(long, help = "Print as JSON")]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    #[command(
+        about = "Print the effective configuration and where each value comes from"
+    )]
+    Show,
+}
+
+fn exit_with_error(message: String) -> ! {
+    eprintln!("Error: {}", message);
+    std::process::exit(1);
 }
 
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
+    let current_dir = env::current_dir().unwrap();
+    if let Some(Command::Trust { revoke }) = args.command {
+        let root = trust::set_trusted(&current_dir, !revoke)
+            .unwrap_or_else(|e| exit_with_error(e));
+        let verb = if revoke {
+            "No longer trusting"
+        } else {
+            "Trusting"
+        };
+        println!("{} {}", verb, root.display());
+        return;
+    }
+    // Only the agent loop asks about trust; the other commands use the
+    // recorded decision. The instruction files to ask about come from the
+    // settings outside the project.
+    let settings = settings::load(
+        args.settings(),
+        args.profile.clone(),
+        false,
+        &current_dir,
+    )
+    .unwrap_or_else(|e| exit_with_error(e));
+    let trusted = trust::check_project(
+        &current_dir,
+        args.command.is_none(),
+        &settings.instructions.files,
+    );
+    let settings = if trusted {
+        settings::load(
+            args.settings(),
+            args.profile.clone(),
+            true,
+            &current_dir,
+        )
+        .unwrap_or_else(|e| exit_with_error(e))
+    } else {
+        settings
+    };
+
+    match args.command {
+        Some(Command::McpServe) => {
+            tools::prompt_approvals_on_tty();
+            let tool_config =
+                yaml_utilities::get_tool_config(&settings, false, false).await;
+            let tools = tools::get_tools(
+                settings.yolo,
+                settings.plan,
+                settings.no_shell,
+                true,
+                &tool_config,
+            );
+            mcp_serve::ToolServer::new(
+                tools,
+                settings.yolo,
+                tool_config.policy.auto_approve.clone(),
+            )
+            .serve()
+            .await
+            .unwrap();
+            return;
+        }
+        Some(Command::Config {
+            command: ConfigCommand::Show,
+        }) => {
+            println!("{}", settings.show());
+            return;
+        }
+        Some(Command::Prompt { json, .. }) | Some(Command::Tools { json }) => {
+            // Inspection needs no API key, so only the provider's model is
+            // looked up. MCP servers are started to list their tools, and
+            // for the prompt only when asked to.
+            let provider_model = providers::default_model(
+                &settings.provider,
+                &settings.providers,
+            );
+            let with_mcp = !matches!(
+                args.command,
+                Some(Command::Prompt { mcp: false, .. })
+            );
+            let tool_config =
+                yaml_utilities::get_tool_config(&settings, false, with_mcp)
+                    .await;
+            let flags = PromptFlags {
+                disable_agents_md: settings.disable_agents_md
+                    || !settings.trusted,
+                plan_mode: settings.plan,
+                yolo: settings.yolo,
+                no_clarify: settings.no_clarify,
+                project_prompt: settings.trusted,
+            };
+            let prompt = request_prompt(
+                &settings.model(provider_model),
+                flags,
+                settings.no_shell,
+                &tool_config,
+                &yaml_utilities::get_response_format(&settings),
+            );
+            let report = match args.command {
+                Some(Command::Prompt { .. }) => {
+                    inspect::prompt_report(&prompt, json)
+                }
+                _ => inspect::tools_report(&prompt.tools, json),
+            };
+            println!("{}", report);
+            return;
+        }
+        Some(Command::Trust { .. }) | None => {}
+    }
+
+    let provider = yaml_utilities::get_provider(&settings)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let backend = chat_backend::create(
+        settings.api.as_deref().unwrap_or(&provider.api),
+        provider.base_url,
+        provider.api_key,
+        provider.headers,
+    )
+    .unwrap_or_else(|e| exit_with_error(e));
+    let model = settings.model(provider.model);
+
     let message =
         args.input.or(args.positional_input).unwrap_or("".to_string()).clone();
 
-    let config_model = yaml_utilities::get_default_model();
-    let model = args.model.as_ref().unwrap_or(&config_model);
+    let response_format = yaml_utilities::get_response_format(&settings);
 
-    let config_format = yaml_utilities::get_default_format();
-    let response_format =
-        yaml_utilities::get_format(args.format.as_deref(), config_format);
+    let tool_config =
+        yaml_utilities::get_tool_config(&settings, !settings.no_stream, true)
+            .await;
 
-    loop_tools_until_finish(
-        message,
-        &model,
-        args.retries,
-        args.max_tokens,
-        args.max_tool_calls,
-        &args.base_url,
-        args.verbose,
-        args.tool_call_details,
-        args.disable_agents_md,
-        args.yolo,
-        args.r#continue,
-        args.plan,
-        args.no_shell,
-        args.no_clarify,
-        &response_format,
-    )
-    .await
-    .unwrap();
+    let options = RunOptions {
+        model,
+        retries: settings.retries,
+        max_tokens: settings.max_tokens,
+        stop: settings.stop(provider.stop),
+        cache_prompt: settings.cache_prompt(provider.cache_prompt),
+        max_tool_calls: settings.max_tool_calls,
+        verbose: settings.verbose,
+        tool_call_details: settings.tool_call_details,
+        disable_agents_md: settings.disable_agents_md,
+        trusted: settings.trusted,
+        yolo: settings.yolo,
+        continue_task: args.r#continue,
+        plan_mode: settings.plan,
+        no_shell: settings.no_shell,
+        no_clarify: settings.no_clarify,
+        tool_response_format: response_format,
+        instructions: settings.instructions.clone(),
+    };
+    loop_tools_until_finish(message, backend.as_ref(), &tool_config, &options)
+        .await
+        .unwrap();
 }
@@ -0,0 +1,398 @@
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Mutex;
+
+const PROTOCOL_VERSION: &str = "2025-06-18";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Deserialize)]
+pub struct McpServerConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    // Treat every tool of this server as read-only: no approval prompt and
+    // available in plan mode.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+struct Connection {
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+    next_id: u64,
+}
+
+// A running MCP server spoken to with newline-delimited JSON-RPC over stdio.
+pub struct McpServer {
+    pub name: String,
+    connection: Mutex<Connection>,
+}
+
+#[derive(Clone)]
+pub struct McpTool {
+    pub server: Arc<McpServer>,
+    // Name exposed to the model, prefixed with the server name.
+    pub name: String,
+    pub remote_name: String,
+    pub description: String,
+    pub input_schema: Value,
+    pub read_only: bool,
+}
+
+impl McpServer {
+    pub async fn start(
+        name: &str,
+        config: &McpServerConfig,
+    ) -> Result<Arc<McpServer>, String> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("cannot start {}: {}", config.command, e))?;
+        let stdin = child.stdin.take().ok_or("no stdin")?;
+        let stdout = child.stdout.take().ok_or("no stdout")?;
+        let server = Arc::new(McpServer {
+            name: name.to_string(),
+            connection: Mutex::new(Connection {
+                _child: child,
+                stdin,
+                stdout: BufReader::new(stdout).lines(),
+                next_id: 1,
+            }),
+        });
+        server
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": {
+                        "name": "attotool",
+                        "version": env!("CARGO_PKG_VERSION")
+                    }
+                }),
+            )
+            .await?;
+        server.notify("notifications/initialized").await?;
+        Ok(server)
+    }
+
+    async fn notify(&self, method: &str) -> Result<(), String> {
+        let mut connection = self.connection.lock().await;
+        let message = json!({"jsonrpc": "2.0", "method": method});
+        send(&mut connection.stdin, &message).await
+    }
+
+    pub async fn request(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, String> {
+        let mut connection = self.connection.lock().await;
+        let id = connection.next_id;
+        connection.next_id += 1;
+        let message = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params
+        });
+        send(&mut connection.stdin, &message).await?;
+        tokio::time::timeout(
+            REQUEST_TIMEOUT,
+            read_response(&mut connection, id),
+        )
+        .await
+        .map_err(|_| format!("{} timed out", method))?
+    }
+
+    pub async fn list_tools(
+        self: &Arc<Self>,
+        read_only: bool,
+    ) -> Result<Vec<McpTool>, String> {
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({"cursor": cursor}),
+                None => json!({}),
+            };
+            let result = self.request("tools/list", params).await?;
+            for tool in result["tools"].as_array().into_iter().flatten() {
+                let Some(remote_name) = tool["name"].as_str() else {
+                    continue;
+                };
+                tools.push(McpTool {
+                    server: self.clone(),
+                    name: format!("{}_{}", self.name, remote_name),
+                    remote_name: remote_name.to_string(),
+                    description: tool["description"]
+                        .as_str()
+                        .unwrap_or("")
+                        .trim()
+                        .to_string(),
+                    input_schema: tool
+                        .get("inputSchema")
+                        .cloned()
+                        .unwrap_or_else(|| json!({"type": "object"})),
+                    // Annotations such as readOnlyHint come from the server
+                    // and are not trusted; only the user's config counts.
+                    read_only,
+                });
+            }
+            match result["nextCursor"].as_str() {
+                Some(next) if !next.is_empty() => {
+                    cursor = Some(next.to_string())
+                }
+                _ => break,
+            }
+        }
+        Ok(tools)
+    }
+}
+
+async fn send(stdin: &mut ChildStdin, message: &Value) -> Result<(), String> {
+    let mut line = message.to_string();
+    line.push('\n');
+    stdin
+        .write_all(line.as_bytes())
+        .await
+        .map_err(|e| format!("cannot write to MCP server: {}", e))?;
+    stdin.flush().await.map_err(|e| e.to_string())
+}
+
+// Reads messages until the response to `id`, answering pings and skipping
+// notifications on the way.
+async fn read_response(
+    connection: &mut Connection,
+    id: u64,
+) -> Result<Value, String> {
+    loop {
+        let line = connection
+            .stdout
+            .next_line()
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or("the MCP server exited")?;
+        let Ok(message) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        if let Some(method) = message["method"].as_str() {
+            if let Some(request_id) = message.get("id") {
+                let reply = if method == "ping" {
+                    json!({"jsonrpc": "2.0", "id": request_id, "result": {}})
+                } else {
+                    json!({
+                        "jsonrpc": "2.0",
+                        "id": request_id,
+                        "error": {"code": -32601, "message": "Method not found"}
+                    })
+                };
+                send(&mut connection.stdin, &reply).await?;
+            }
+            continue;
+        }
+        if message["id"].as_u64() != Some(id) {
+            continue;
+        }
+        if let Some(error) = message.get("error") {
+            return Err(error["message"]
+                .as_str()
+                .map(|m| m.to_string())
+                .unwrap_or_else(|| error.to_string()));
+        }
+        return Ok(message["result"].clone());
+    }
+}
+
+impl McpTool {
+    // Flattens the top-level properties of the input schema into the
+    // (name, type) pairs used for built-in tools in the system prompt.
+    pub fn parameters(&self) -> Vec<(String, String)> {
+        let required: Vec<&str> = self.input_schema["required"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|r| r.as_str())
+            .collect();
+        self.input_schema["properties"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(name, schema)| {
+                let mut kind = match schema["type"].as_str() {
+                    Some("array") => match schema["items"]["type"].as_str() {
+                        Some(item) => format!("array of {}s", item),
+                        None => "array".to_string(),
+                    },
+                    Some(kind) => kind.to_string(),
+                    None => "any".to_string(),
+                };
+                if !required.contains(&name.as_str()) {
+                    kind.push_str(" (optional)");
+                }
+                if let Some(description) = schema["description"].as_str() {
+                    kind.push_str(&format!(" - {}", description));
+                }
+                (name.clone(), kind)
+            })
+            .collect()
+    }
+
+    pub async fn call(&self, arguments: Value) -> Result<String, String> {
+        let arguments = match arguments {
+            Value::Object(_) => arguments,
+            _ => json!({}),
+        };
+        let result = self
+            .server
+            .request(
+                "tools/call",
+                json!({"name": self.remote_name, "arguments": arguments}),
+            )
+            .await?;
+        let text = result["content"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|item| match item["type"].as_str() {
+                Some("text") => item["text"].as_str().unwrap_or("").to_string(),
+                Some("resource") => item["resource"]["text"]
+                    .as_str()
+                    .map(|t| t.to_string())
+                    .unwrap_or_else(|| {
+                        format!("[resource {}]", item["resource"]["uri"])
+                    }),
+                Some(kind) => format!("[{} content omitted]", kind),
+                None => String::new(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        if result["isError"].as_bool().unwrap_or(false) {
+            return Err(text);
+        }
+        Ok(text)
+    }
+}
+
+// Starts every configured server and collects its tools. Servers that fail
+// to start are reported and skipped so that attotool itself still runs.
+pub async fn load_tools(
+    servers: &BTreeMap<String, McpServerConfig>,
+) -> Vec<McpTool> {
+    let mut tools = Vec::new();
+    for (name, config) in servers {
+        let listed = match McpServer::start(name, config).await {
+            Ok(server) => server.list_tools(config.read_only).await,
+            Err(e) => Err(e),
+        };
+        match listed {
+            Ok(server_tools) => tools.extend(server_tools),
+            Err(e) => {
+                eprintln!(
+                    "Warning: MCP server `{}` is unavailable: {}",
+                    name, e
+                )
+            }
+        }
+    }
+    tools
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::tools::{Tool, ToolConfig, get_tools};
+
+    // A canned MCP server: it answers the requests attotool sends, in
+    // order, and records the tools/call request it receives.
+    const FAKE_SERVER: &str = r#"
+read -r line
+echo '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2025-06-18","capabilities":{"tools":{}},"serverInfo":{"name":"fake","version":"1"}}}'
+read -r line
+read -r line
+echo '{"jsonrpc":"2.0","method":"notifications/message","params":{}}'
+echo '{"jsonrpc":"2.0","id":2,"result":{"tools":[{"name":"lookup","description":"Looks up an issue","inputSchema":{"type":"object","properties":{"id":{"type":"integer","description":"Issue number"},"fields":{"type":"array","items":{"type":"string"}}},"required":["id"]},"annotations":{"readOnlyHint":true}}]}}'
+read -r line
+echo "$line" > "$CALL_LOG"
+echo '{"jsonrpc":"2.0","id":3,"result":{"content":[{"type":"text","text":"Issue 7: crash on start"}]}}'
+read -r line
+"#;
+
+    #[tokio::test]
+    async fn test_load_tools_and_call() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("call.json");
+        let mut servers = BTreeMap::new();
+        servers.insert(
+            "issues".to_string(),
+            McpServerConfig {
+                command: "sh".to_string(),
+                args: vec!["-c".to_string(), FAKE_SERVER.to_string()],
+                env: BTreeMap::from([(
+                    "CALL_LOG".to_string(),
+                    log.to_string_lossy().to_string(),
+                )]),
+                read_only: false,
+            },
+        );
+        let tools = load_tools(&servers).await;
+        assert_eq!(tools.len(), 1);
+        let tool = &tools[0];
+        assert_eq!(tool.name, "issues_lookup");
+        // The server's readOnlyHint is ignored.
+        assert!(!tool.read_only);
+        assert_eq!(
+            tool.parameters(),
+            vec![
+                (
+                    "fields".to_string(),
+                    "array of strings (optional)".to_string()
+                ),
+                ("id".to_string(), "integer - Issue number".to_string()),
+            ]
+        );
+        let output = tool.call(json!({"id": 7})).await.unwrap();
+        assert_eq!(output, "Issue 7: crash on start");
+        let call: Value =
+            serde_json::from_str(&std::fs::read_to_string(&log).unwrap())
+                .unwrap();
+        assert_eq!(call["method"], "tools/call");
+        assert_eq!(call["params"]["name"], "lookup");
+        assert_eq!(call["params"]["arguments"]["id"], 7);
+
+        // A server `read` with a tool `file` must not shadow read_file.
+        let clashing = McpTool {
+            name: "read_file".to_string(),
+            ..tool.clone()
+        };
+        let config = ToolConfig {
+            mcp_tools: vec![clashing, tool.clone()],
+            ..Default::default()
+        };
+        let offered = get_tools(false, false, false, false, &config);
+        assert_eq!(
+            offered.iter().filter(|t| t.name() == "read_file").count(),
+            1
+        );
+        assert!(
+            offered
+                .iter()
+                .any(|t| matches!(t, Tool::ReadFile) && t.name() == "read_file")
+        );
+        assert!(offered.iter().any(|t| t.name() == "issues_lookup"));
+    }
+}
@@ -0,0 +1,203 @@
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use crate::tools::Tool;
+
+const PROTOCOL_VERSION: &str = "2025-06-18";
+
+// Serves attotool's own tools to an MCP client over stdio. Approval prompts
+// still apply: they are asked on the controlling terminal, not over stdio.
+pub struct ToolServer {
+    tools: Vec<Tool>,
+    yolo: bool,
+    auto_approve: Vec<String>,
+}
+
+fn response(id: &Value, result: Value) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "result": result})
+}
+
+fn error(id: &Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {"code": code, "message": message}
+    })
+}
+
+impl ToolServer {
+    pub fn new(
+        tools: Vec<Tool>,
+        yolo: bool,
+        auto_approve: Vec<String>,
+    ) -> Self {
+        // Tools that only make sense inside attotool's own agent loop.
+        let tools = tools
+            .into_iter()
+            .filter(|t| {
+                !matches!(
+                    t,
+                    Tool::FinishTask
+                        | Tool::FinishPlanning
+                        | Tool::AskForClarification
+                        | Tool::DescribeToUser
+                        | Tool::Mcp(_)
+                )
+            })
+            .collect();
+        ToolServer {
+            tools,
+            yolo,
+            auto_approve,
+        }
+    }
+
+    // Returns the reply to `message`, or None for notifications.
+    pub async fn handle(&self, message: &Value) -> Option<Value> {
+        let method = message["method"].as_str()?;
+        let id = message.get("id")?;
+        let params = &message["params"];
+        let reply = match method {
+            "initialize" => response(
+                id,
+                json!({
+                    "protocolVersion": params["protocolVersion"]
+                        .as_str()
+                        .unwrap_or(PROTOCOL_VERSION),
+                    "capabilities": {"tools": {}},
+                    "serverInfo": {
+                        "name": "attotool",
+                        "version": env!("CARGO_PKG_VERSION")
+                    }
+                }),
+            ),
+            "ping" => response(id, json!({})),
+            "tools/list" => {
+                let tools: Vec<Value> = self
+                    .tools
+                    .iter()
+                    .map(|t| {
+                        json!({
+                            "name": t.name(),
+                            "description": t.description(),
+                            "inputSchema": t.args_schema()
+                        })
+                    })
+                    .collect();
+                response(id, json!({"tools": tools}))
+            }
+            "tools/call" => {
+                let name = params["name"].as_str().unwrap_or("");
+                let Some(tool) = self.tools.iter().find(|t| t.name() == name)
+                else {
+                    return Some(error(
+                        id,
+                        -32602,
+                        &format!("Unknown tool: {}", name),
+                    ));
+                };
+                let args = match &params["arguments"] {
+                    Value::Object(_) => params["arguments"].clone(),
+                    _ => json!({}),
+                };
+                let yolo =
+                    self.yolo || self.auto_approve.iter().any(|t| t == name);
+                let (text, is_error) =
+                    match tool.execute(args, false, yolo).await {
+                        Ok(text) => (text, false),
+                        Err(e) => (e.to_string(), true),
+                    };
+                response(
+                    id,
+                    json!({
+                        "content": [{"type": "text", "text": text}],
+                        "isError": is_error
+                    }),
+                )
+            }
+            _ => error(id, -32601, &format!("Method not found: {}", method)),
+        };
+        Some(reply)
+    }
+
+    pub async fn serve(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        let mut stdout = tokio::io::stdout();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let reply = match serde_json::from_str::<Value>(&line) {
+                Ok(message) => self.handle(&message).await,
+                Err(e) => Some(error(&Value::Null, -32700, &e.to_string())),
+            };
+            if let Some(reply) = reply {
+                stdout.write_all(format!("{}\n", reply).as_bytes()).await?;
+                stdout.flush().await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::{ToolConfig, get_tools};
+
+    #[tokio::test]
+    async fn test_tool_server_lists_and_calls_tools() {
+        let config = ToolConfig::default();
+        let server = ToolServer::new(
+            get_tools(true, false, false, true, &config),
+            true,
+            Vec::new(),
+        );
+        let reply = server
+            .handle(&json!({"jsonrpc": "2.0", "id": 1, "method": "initialize",
+                "params": {"protocolVersion": "2025-03-26"}}))
+            .await
+            .unwrap();
+        assert_eq!(reply["result"]["protocolVersion"], "2025-03-26");
+        assert!(
+            server
+                .handle(&json!({"jsonrpc": "2.0",
+                    "method": "notifications/initialized"}))
+                .await
+                .is_none()
+        );
+
+        let reply = server
+            .handle(&json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}))
+            .await
+            .unwrap();
+        let names: Vec<&str> = reply["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["name"].as_str().unwrap())
+            .collect();
+        assert!(names.contains(&"read_file"));
+        assert!(names.contains(&"execute_shell_command"));
+        assert!(!names.contains(&"finish_task"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hello.txt");
+        std::fs::write(&path, "hello\n").unwrap();
+        let reply = server
+            .handle(&json!({"jsonrpc": "2.0", "id": 3, "method": "tools/call",
+                "params": {"name": "read_file",
+                    "arguments": {"path": path.to_str().unwrap()}}}))
+            .await
+            .unwrap();
+        assert_eq!(reply["result"]["content"][0]["text"], "     1\thello");
+        assert_eq!(reply["result"]["isError"], false);
+
+        let reply = server
+            .handle(&json!({"jsonrpc": "2.0", "id": 4, "method": "tools/call",
+                "params": {"name": "nope"}}))
+            .await
+            .unwrap();
+        assert_eq!(reply["error"]["code"], -32602);
+    }
+}
@@ -0,0 +1,609 @@
+// Parses and applies patches sent by the model. Two formats are accepted:
+// standard unified diffs (as produced by `git diff` or `diff -u`) and a
+// simplified envelope that names each file explicitly:
+//
+//   *** Begin Patch
+//   *** Add File: path/to/new.txt
+//   +first line
+//   *** Update File: path/to/existing.rs
+//   @@
+//    context line
+//   -removed line
+//   +added line
+//   *** Delete File: path/to/old.txt
+//   *** End Patch
+
+use crate::edits::{FileChange, TextFormat};
+use crate::transaction;
+use std::path::PathBuf;
+
+#[derive(Debug, PartialEq)]
+pub enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Hunk {
+    // 1-based line in the original file where the hunk starts, if known
+    pub old_start: Option<usize>,
+    pub lines: Vec<HunkLine>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FilePatch {
+    Add { path: String, content: String },
+    Delete { path: String },
+    Update { path: String, hunks: Vec<Hunk> },
+}
+
+impl FilePatch {
+    pub fn path(&self) -> &str {
+        match self {
+            FilePatch::Add { path, .. }
+            | FilePatch::Delete { path }
+            | FilePatch::Update { path, .. } => path,
+        }
+    }
+}
+
+pub fn parse_patch(text: &str) -> Result<Vec<FilePatch>, String> {
+    let patches = if text.contains("*** Begin Patch") {
+        parse_envelope(text)?
+    } else {
+        parse_unified(text)?
+    };
+    if patches.is_empty() {
+        return Err("the patch does not contain any file changes".to_string());
+    }
+    Ok(patches)
+}
+
+fn parse_hunk_line(line: &str) -> Option<HunkLine> {
+    if let Some(rest) = line.strip_prefix('+') {
+        Some(HunkLine::Add(rest.to_string()))
+    } else if let Some(rest) = line.strip_prefix('-') {
+        Some(HunkLine::Remove(rest.to_string()))
+    } else if let Some(rest) = line.strip_prefix(' ') {
+        Some(HunkLine::Context(rest.to_string()))
+    } else if line.is_empty() {
+        // Models often drop the leading space of blank context lines.
+        Some(HunkLine::Context(String::new()))
+    } else {
+        None
+    }
+}
+
+fn parse_hunk_header(line: &str) -> Option<usize> {
+    // @@ -12,7 +12,8 @@ optional section heading
+    let old = line.strip_prefix("@@ -")?.split_whitespace().next()?;
+    old.split(',').next()?.parse().ok()
+}
+
+// The old and new line counts of a `@@ -12,7 +12,8 @@` header; an omitted
+// count means one line.
+fn parse_hunk_counts(line: &str) -> Option<(usize, usize)> {
+    let mut ranges = line.strip_prefix("@@ -")?.split_whitespace();
+    let count = |range: &str| match range.split_once(',') {
+        Some((_, count)) => count.parse().ok(),
+        None => Some(1),
+    };
+    let old = count(ranges.next()?)?;
+    let new = count(ranges.next()?.strip_prefix('+')?)?;
+    Some((old, new))
+}
+
+fn strip_diff_prefix(path: &str) -> String {
+    let path = path.split('\t').next().unwrap_or(path).trim();
+    path.strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path)
+        .to_string()
+}
+
+fn parse_unified(text: &str) -> Result<Vec<FilePatch>, String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut patches = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let is_header = lines[i].starts_with("--- ")
+            && lines.get(i + 1).is_some_and(|l| l.starts_with("+++ "));
+        if !is_header {
+            i += 1;
+            continue;
+        }
+        let old_path = lines[i][4..].trim();
+        let new_path = lines[i + 1][4..].trim();
+        i += 2;
+
+        let mut hunks: Vec<Hunk> = Vec::new();
+        // Old and new lines the current hunk header still promises, and
+        // blank lines seen after it had them all.
+        let mut remaining: Option<(usize, usize)> = None;
+        let mut trailing_blanks = 0;
+        while i < lines.len() {
+            let line = lines[i];
+            let next_file = line.starts_with("--- ")
+                && lines.get(i + 1).is_some_and(|l| l.starts_with("+++ "));
+            // Inside a hunk its counts decide, so removing `-- x` and adding
+            // `++ y` is not taken for the next file's header.
+            let in_hunk = remaining.is_some_and(|r| r != (0, 0));
+            if !in_hunk && (next_file || line.starts_with("diff ")) {
+                break;
+            }
+            if line.starts_with("@@") {
+                hunks.push(Hunk {
+                    old_start: parse_hunk_header(line),
+                    lines: Vec::new(),
+                });
+                remaining = parse_hunk_counts(line);
+                trailing_blanks = 0;
+            } else if line.starts_with('\\') {
+                // "\ No newline at end of file"
+            } else if line.is_empty() && remaining == Some((0, 0)) {
+                trailing_blanks += 1;
+            } else if let Some(hunk) = hunks.last_mut() {
+                match parse_hunk_line(line) {
+                    Some(hunk_line) => {
+                        // More lines than the header announced: the counts
+                        // were wrong, so the blank lines belonged to the hunk.
+                        for _ in 0..trailing_blanks {
+                            hunk.lines.push(HunkLine::Context(String::new()));
+                        }
+                        trailing_blanks = 0;
+                        if let Some((old, new)) = remaining.as_mut() {
+                            if !matches!(hunk_line, HunkLine::Add(_)) {
+                                *old = old.saturating_sub(1);
+                            }
+                            if !matches!(hunk_line, HunkLine::Remove(_)) {
+                                *new = new.saturating_sub(1);
+                            }
+                        }
+                        hunk.lines.push(hunk_line)
+                    }
+                    None => {
+                        return Err(format!(
+                            "unexpected line in hunk for {}: '{}'",
+                            new_path, line
+                        ));
+                    }
+                }
+            }
+            i += 1;
+        }
+
+        if new_path.starts_with("/dev/null") {
+            patches.push(FilePatch::Delete {
+                path: strip_diff_prefix(old_path),
+            });
+        } else if old_path.starts_with("/dev/null") {
+            let content = hunks
+                .iter()
+                .flat_map(|h| h.lines.iter())
+                .filter_map(|l| match l {
+                    HunkLine::Add(s) => Some(format!("{}\n", s)),
+                    _ => None,
+                })
+                .collect();
+            patches.push(FilePatch::Add {
+                path: strip_diff_prefix(new_path),
+                content,
+            });
+        } else {
+            if hunks.is_empty() {
+                return Err(format!("no hunks found for {}", new_path));
+            }
+            patches.push(FilePatch::Update {
+                path: strip_diff_prefix(new_path),
+                hunks,
+            });
+        }
+    }
+    Ok(patches)
+}
+
+fn parse_envelope(text: &str) -> Result<Vec<FilePatch>, String> {
+    let mut patches = Vec::new();
+    let mut lines = text
+        .lines()
+        .skip_while(|l| !l.starts_with("*** Begin Patch"))
+        .skip(1)
+        .peekable();
+    while let Some(line) = lines.next() {
+        if line.starts_with("*** End Patch") {
+            break;
+        } else if let Some(path) = line.strip_prefix("*** Add File: ") {
+            let mut content = String::new();
+            while let Some(next) = lines.peek() {
+                if next.starts_with("*** ") {
+                    break;
+                }
+                let next = lines.next().unwrap_or_default();
+                content.push_str(next.strip_prefix('+').unwrap_or(next));
+                content.push('\n');
+            }
+            patches.push(FilePatch::Add {
+                path: path.trim().to_string(),
+                content,
+            });
+        } else if let Some(path) = line.strip_prefix("*** Delete File: ") {
+            patches.push(FilePatch::Delete {
+                path: path.trim().to_string(),
+            });
+        } else if let Some(path) = line.strip_prefix("*** Update File: ") {
+            let mut hunks: Vec<Hunk> = Vec::new();
+            while let Some(next) = lines.peek() {
+                if next.starts_with("*** End of File") {
+                    lines.next();
+                    continue;
+                }
+                if next.starts_with("*** ") {
+                    break;
+                }
+                let next = lines.next().unwrap_or_default();
+                if next.starts_with("@@") {
+                    hunks.push(Hunk {
+                        old_start: parse_hunk_header(next),
+                        lines: Vec::new(),
+                    });
+                    continue;
+                }
+                if hunks.is_empty() {
+                    hunks.push(Hunk {
+                        old_start: None,
+                        lines: Vec::new(),
+                    });
+                }
+                match parse_hunk_line(next) {
+                    Some(hunk_line) => {
+                        hunks.last_mut().unwrap().lines.push(hunk_line)
+                    }
+                    None => {
+                        return Err(format!(
+                            "unexpected line in update of {}: '{}'",
+                            path.trim(),
+                            next
+                        ));
+                    }
+                }
+            }
+            hunks.retain(|h| !h.lines.is_empty());
+            if hunks.is_empty() {
+                return Err(format!("no changes found for {}", path.trim()));
+            }
+            patches.push(FilePatch::Update {
+                path: path.trim().to_string(),
+                hunks,
+            });
+        } else if !line.trim().is_empty() {
+            return Err(format!("unexpected line in patch: '{}'", line));
+        }
+    }
+    Ok(patches)
+}
+
+type LineEq = fn(&str, &str) -> bool;
+
+// Successively looser ways of comparing a hunk line with a file line.
+const MATCH_PASSES: [(&str, LineEq); 3] = [
+    ("exact", |a, b| a == b),
+    ("ignoring trailing whitespace", |a, b| {
+        a.trim_end() == b.trim_end()
+    }),
+    ("ignoring indentation", |a, b| a.trim() == b.trim()),
+];
+
+fn find_block(
+    lines: &[String],
+    block: &[&str],
+    cursor: usize,
+    hint: Option<usize>,
+    eq: LineEq,
+) -> Option<usize> {
+    if block.len() > lines.len() {
+        return None;
+    }
+    let matches_at = |start: usize| {
+        block.iter().enumerate().all(|(i, b)| eq(&lines[start + i], b))
+    };
+    // Hunks apply in order, so only lines after the previous hunk are
+    // searched.
+    let candidates =
+        (cursor..=lines.len() - block.len()).filter(|&p| matches_at(p));
+    match hint {
+        Some(hint) => {
+            candidates.min_by_key(|p| (*p as isize - hint as isize).abs())
+        }
+        None => candidates.min(),
+    }
+}
+
+// Applies `hunks` to `content`. Returns the new content and a note for every
+// hunk that only matched fuzzily.
+pub fn apply_hunks(
+    path: &str,
+    content: &str,
+    hunks: &[Hunk],
+) -> Result<(String, Vec<String>), String> {
+    let had_trailing_newline = content.ends_with('\n');
+    let mut lines: Vec<String> =
+        content.lines().map(|l| l.to_string()).collect();
+    let mut notes = Vec::new();
+    let mut cursor = 0;
+    for (index, hunk) in hunks.iter().enumerate() {
+        let old: Vec<&str> = hunk
+            .lines
+            .iter()
+            .filter_map(|l| match l {
+                HunkLine::Context(s) | HunkLine::Remove(s) => Some(s.as_str()),
+                HunkLine::Add(_) => None,
+            })
+            .collect();
+        let new: Vec<String> = hunk
+            .lines
+            .iter()
+            .filter_map(|l| match l {
+                HunkLine::Context(s) | HunkLine::Add(s) => Some(s.clone()),
+                HunkLine::Remove(_) => None,
+            })
+            .collect();
+        let hint = hunk.old_start.map(|s| s.saturating_sub(1));
+
+        let position = if old.is_empty() {
+            Some(hint.unwrap_or(lines.len()).min(lines.len()))
+        } else {
+            MATCH_PASSES.iter().enumerate().find_map(|(pass, (name, eq))| {
+                let found = find_block(&lines, &old, cursor, hint, *eq)?;
+                if pass > 0 {
+                    notes.push(format!(
+                        "hunk {} of {} matched {}",
+                        index + 1,
+                        path,
+                        name
+                    ));
+                }
+                Some(found)
+            })
+        };
+        let Some(position) = position else {
+            return Err(format!(
+                "hunk {} of {} did not match the file. Expected to find these lines:\n{}",
+                index + 1,
+                path,
+                old.join("\n")
+            ));
+        };
+        let new_len = new.len();
+        lines.splice(position..position + old.len(), new);
+        cursor = position + new_len;
+    }
+    let mut result = lines.join("\n");
+    if had_trailing_newline || content.is_empty() {
+        result.push('\n');
+    }
+    Ok((result, notes))
+}
+
+// Resolves parsed patches against the files on disk, without writing
+// anything. Several patches to the same file are applied in order.
+pub fn resolve_patches(
+    patches: &[FilePatch],
+) -> Result<(Vec<FileChange>, Vec<String>), String> {
+    let mut changes: Vec<FileChange> = Vec::new();
+    let mut notes = Vec::new();
+    for patch in patches {
+        let path = PathBuf::from(patch.path());
+        let index = match changes.iter().position(|c| c.path == path) {
+            Some(index) => index,
+            None => {
+                let original = match transaction::read_current(&path) {
+                    Ok(content) => Some(content),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                    Err(e) => {
+                        return Err(format!(
+                            "cannot read {}: {}",
+                            path.display(),
+                            e
+                        ));
+                    }
+                };
+                changes.push(FileChange {
+                    path: path.clone(),
+                    updated: original.clone(),
+                    original,
+                });
+                changes.len() - 1
+            }
+        };
+        let change = &mut changes[index];
+        match patch {
+            FilePatch::Add { content, .. } => {
+                if change.updated.is_some() {
+                    return Err(format!(
+                        "cannot add {}: the file already exists",
+                        path.display()
+                    ));
+                }
+                change.updated = Some(content.clone());
+            }
+            FilePatch::Delete { .. } => {
+                if change.updated.is_none() {
+                    return Err(format!(
+                        "cannot delete {}: the file does not exist",
+                        path.display()
+                    ));
+                }
+                change.updated = None;
+            }
+            FilePatch::Update { hunks, .. } => {
+                let Some(current) = &change.updated else {
+                    return Err(format!(
+                        "cannot update {}: the file does not exist",
+                        path.display()
+                    ));
+                };
+                let (text, format) = TextFormat::normalize(current);
+                let (updated, hunk_notes) =
+                    apply_hunks(patch.path(), &text, hunks)?;
+                change.updated = Some(format.render(&updated));
+                notes.extend(hunk_notes);
+            }
+        }
+    }
+    changes.retain(|c| c.original != c.updated);
+    Ok((changes, notes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_parse_unified_diff_multiple_files() {
+        let diff = "diff --git a/src/a.rs b/src/a.rs\n--- a/src/a.rs\n+++ b/src/a.rs\n@@ -1,3 +1,3 @@\n fn a() {\n-    1\n+    2\n }\n\n--- /dev/null\n+++ b/new.txt\n@@ -0,0 +1,2 @@\n+hello\n+world\n--- a/old.txt\n+++ /dev/null\n@@ -1 +0,0 @@\n-bye\n";
+        let patches = parse_patch(diff).unwrap();
+        assert_eq!(patches.len(), 3);
+        assert_eq!(
+            patches[0],
+            FilePatch::Update {
+                path: "src/a.rs".to_string(),
+                hunks: vec![Hunk {
+                    old_start: Some(1),
+                    lines: vec![
+                        HunkLine::Context("fn a() {".to_string()),
+                        HunkLine::Remove("    1".to_string()),
+                        HunkLine::Add("    2".to_string()),
+                        HunkLine::Context("}".to_string()),
+                    ],
+                }],
+            }
+        );
+        assert_eq!(
+            patches[1],
+            FilePatch::Add {
+                path: "new.txt".to_string(),
+                content: "hello\nworld\n".to_string(),
+            }
+        );
+        assert_eq!(
+            patches[2],
+            FilePatch::Delete {
+                path: "old.txt".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_unified_hunk_removing_dash_comment() {
+        let diff = "--- a/x.lua\n+++ b/x.lua\n@@ -1,2 +1,2 @@\n--- counter\n+++i;\n end\n";
+        let patches = parse_patch(diff).unwrap();
+        assert_eq!(
+            patches,
+            vec![FilePatch::Update {
+                path: "x.lua".to_string(),
+                hunks: vec![Hunk {
+                    old_start: Some(1),
+                    lines: vec![
+                        HunkLine::Remove("-- counter".to_string()),
+                        HunkLine::Add("++i;".to_string()),
+                        HunkLine::Context("end".to_string()),
+                    ],
+                }],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_envelope_patch() {
+        let patch = "*** Begin Patch\n*** Add File: notes.md\n+# Notes\n*** Update File: src/lib.rs\n@@\n-old\n+new\n*** Delete File: tmp.txt\n*** End Patch";
+        let patches = parse_patch(patch).unwrap();
+        assert_eq!(
+            patches.iter().map(|p| p.path()).collect::<Vec<_>>(),
+            vec!["notes.md", "src/lib.rs", "tmp.txt"]
+        );
+        assert_eq!(
+            patches[0],
+            FilePatch::Add {
+                path: "notes.md".to_string(),
+                content: "# Notes\n".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_apply_hunks_uses_hint_for_repeated_context() {
+        let content = "x\ny\nx\ny\n";
+        let hunks = vec![Hunk {
+            old_start: Some(3),
+            lines: vec![
+                HunkLine::Context("x".to_string()),
+                HunkLine::Remove("y".to_string()),
+                HunkLine::Add("z".to_string()),
+            ],
+        }];
+        let (result, notes) = apply_hunks("f", content, &hunks).unwrap();
+        assert_eq!(result, "x\ny\nx\nz\n");
+        assert!(notes.is_empty());
+    }
+
+    #[test]
+    fn test_apply_hunks_fuzzy_whitespace_and_failure() {
+        let content = "fn a() {\n    let x = 1;  \n}\n";
+        let hunks = vec![Hunk {
+            old_start: None,
+            lines: vec![
+                HunkLine::Remove("  let x = 1;".to_string()),
+                HunkLine::Add("    let x = 2;".to_string()),
+            ],
+        }];
+        let (result, notes) = apply_hunks("f", content, &hunks).unwrap();
+        assert_eq!(result, "fn a() {\n    let x = 2;\n}\n");
+        assert_eq!(notes, vec!["hunk 1 of f matched ignoring indentation"]);
+
+        let hunks = vec![Hunk {
+            old_start: None,
+            lines: vec![HunkLine::Remove("missing".to_string())],
+        }];
+        let err = apply_hunks("f", content, &hunks).unwrap_err();
+        assert!(err.starts_with("hunk 1 of f did not match"));
+    }
+
+    #[test]
+    fn test_apply_hunks_never_matches_above_an_earlier_hunk() {
+        let content = "a\nb\nc\n";
+        let hunk = |old: &str, new: &str| Hunk {
+            old_start: None,
+            lines: vec![
+                HunkLine::Remove(old.to_string()),
+                HunkLine::Add(new.to_string()),
+            ],
+        };
+        let hunks = vec![hunk("b", "B"), hunk("a", "A")];
+        let err = apply_hunks("f", content, &hunks).unwrap_err();
+        assert!(err.starts_with("hunk 2 of f did not match"));
+        let hunks = vec![hunk("a", "A"), hunk("c", "C")];
+        let (result, _) = apply_hunks("f", content, &hunks).unwrap();
+        assert_eq!(result, "A\nb\nC\n");
+    }
+
+    #[test]
+    fn test_resolve_patches_preserves_crlf_and_missing_final_newline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("win.txt");
+        fs::write(&path, "one\r\ntwo\r\nthree").unwrap();
+        let patches = vec![FilePatch::Update {
+            path: path.to_string_lossy().to_string(),
+            hunks: vec![Hunk {
+                old_start: None,
+                lines: vec![
+                    HunkLine::Remove("two".to_string()),
+                    HunkLine::Add("TWO".to_string()),
+                ],
+            }],
+        }];
+        let (changes, _) = resolve_patches(&patches).unwrap();
+        assert_eq!(changes[0].updated.as_deref(), Some("one\r\nTWO\r\nthree"));
+    }
+}
@@ -0,0 +1,220 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::process::Command;
+
+#[derive(Clone, Default, Deserialize)]
+pub struct ProviderConfig {
+    // API spoken by the endpoint: openai, anthropic or ollama.
+    pub api: Option<String>,
+    pub base_url: Option<String>,
+    pub api_key_env: Option<String>,
+    // Shell command printing the key, e.g. `pass show openrouter`.
+    pub api_key_command: Option<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub model: Option<String>,
+    // Defaults for the `stop` and `cache_prompt` settings.
+    pub stop: Option<Vec<String>>,
+    pub cache_prompt: Option<bool>,
+}
+
+pub struct Provider {
+    pub api: String,
+    pub base_url: Option<String>,
+    pub api_key: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub model: Option<String>,
+    pub stop: Option<Vec<String>>,
+    pub cache_prompt: Option<bool>,
+}
+
+fn builtin(name: &str) -> Option<ProviderConfig> {
+    let (api, base_url, key) = match name {
+        "openrouter" => (
+            "openai",
+            Some("https://openrouter.ai/api/v1"),
+            Some("OPENROUTER_API_KEY"),
+        ),
+        "openai" => (
+            "openai",
+            Some("https://api.openai.com/v1"),
+            Some("OPENAI_API_KEY"),
+        ),
+        "anthropic" => (
+            "anthropic",
+            Some("https://api.anthropic.com"),
+            Some("ANTHROPIC_API_KEY"),
+        ),
+        "ollama" => ("ollama", Some("http://localhost:11434"), None),
+        _ => return None,
+    };
+    Some(ProviderConfig {
+        api: Some(api.to_string()),
+        base_url: base_url.map(|u| u.to_string()),
+        api_key_env: key.map(|k| k.to_string()),
+        ..Default::default()
+    })
+}
+
+fn run_key_command(name: &str, command: &str) -> Result<String, String> {
+    let output =
+        Command::new("sh").args(["-c", command]).output().map_err(|e| {
+            format!("provider `{}`: cannot run api_key_command: {}", name, e)
+        })?;
+    let key = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || key.is_empty() {
+        return Err(format!(
+            "provider `{}`: api_key_command `{}` did not print a key{}",
+            name,
+            command,
+            match String::from_utf8_lossy(&output.stderr).trim() {
+                "" => String::new(),
+                stderr => format!(": {}", stderr),
+            }
+        ));
+    }
+    Ok(key)
+}
+
+fn key_from_env(
+    name: &str,
+    var: &str,
+    required: bool,
+) -> Result<Option<String>, String> {
+    match env::var(var) {
+        Ok(key) => Ok(Some(key)),
+        Err(_) if !required => Ok(None),
+        Err(_) => {
+            Err(format!("provider `{}` needs an API key: set {}", name, var))
+        }
+    }
+}
+
+// The default model of provider `name`, without resolving its API key.
+pub fn default_model(
+    name: &str,
+    configured: &BTreeMap<String, ProviderConfig>,
+) -> Option<String> {
+    configured
+        .get(name)
+        .and_then(|config| config.model.clone())
+        .or_else(|| builtin(name)?.model)
+}
+
+// Looks up `name` among the configured providers, falling back to the
+// built-in entry of the same name for any field left unset. With a
+// `base_url` override (--base-url) a missing key is not an error, since the
+// endpoint may be a local server that needs none.
+pub fn resolve(
+    name: &str,
+    configured: &BTreeMap<String, ProviderConfig>,
+    base_url: Option<String>,
+) -> Result<Provider, String> {
+    let required = base_url.is_none();
+    let base = builtin(name).unwrap_or_default();
+    let config = match configured.get(name) {
+        Some(config) => config.clone(),
+        None if builtin(name).is_some() => ProviderConfig::default(),
+        None => {
+            let mut known: Vec<&str> =
+                vec!["openrouter", "openai", "anthropic", "ollama"];
+            known.extend(configured.keys().map(|k| k.as_str()));
+            return Err(format!(
+                "unknown provider `{}`; known providers: {}",
+                name,
+                known.join(", ")
+            ));
+        }
+    };
+    let api_key = match (&config.api_key_command, &config.api_key_env) {
+        (Some(command), _) => Some(run_key_command(name, command)?),
+        (None, Some(var)) => key_from_env(name, var, required)?,
+        // A configured base URL without key settings is a local server
+        // that needs no key, not the built-in provider's endpoint.
+        (None, None) if config.base_url.is_some() => None,
+        (None, None) => match &base.api_key_env {
+            Some(var) => key_from_env(name, var, required)?,
+            None => None,
+        },
+    };
+    let mut headers = base.headers;
+    headers.extend(config.headers);
+    Ok(Provider {
+        api: config.api.or(base.api).unwrap_or_else(|| "openai".to_string()),
+        base_url: base_url.or(config.base_url).or(base.base_url),
+        api_key,
+        headers: headers.into_iter().collect(),
+        model: config.model.or(base.model),
+        stop: config.stop.or(base.stop),
+        cache_prompt: config.cache_prompt.or(base.cache_prompt),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_merges_builtin_and_reads_keys() {
+        let configured: BTreeMap<String, ProviderConfig> =
+            serde_yaml::from_str(
+                r#"
+openrouter:
+  api_key_command: echo sk-from-command
+  headers:
+    X-Title: attotool
+  model: mistralai/mistral-small-3.1-24b-instruct
+  stop: ["\n\n\n"]
+local:
+  base_url: http://localhost:8080/v1
+work:
+  api: anthropic
+  api_key_env: ATTOTOOL_TEST_UNSET_KEY
+"#,
+            )
+            .unwrap();
+
+        let provider = resolve("openrouter", &configured, None).unwrap();
+        assert_eq!(provider.api, "openai");
+        assert_eq!(
+            provider.base_url.as_deref(),
+            Some("https://openrouter.ai/api/v1")
+        );
+        assert_eq!(provider.api_key.as_deref(), Some("sk-from-command"));
+        assert_eq!(provider.stop, Some(vec!["\n\n\n".to_string()]));
+        assert_eq!(provider.cache_prompt, None);
+        assert_eq!(
+            provider.headers,
+            vec![("X-Title".to_string(), "attotool".to_string())]
+        );
+
+        let local = resolve("local", &configured, None).unwrap();
+        assert_eq!(local.api, "openai");
+        assert!(local.api_key.is_none());
+
+        let ollama = resolve("ollama", &configured, None).unwrap();
+        assert_eq!(ollama.api, "ollama");
+        assert!(ollama.api_key.is_none());
+
+        let err = resolve("work", &configured, None).err().unwrap();
+        assert_eq!(
+            err,
+            "provider `work` needs an API key: set ATTOTOOL_TEST_UNSET_KEY"
+        );
+        let overridden = resolve(
+            "work",
+            &configured,
+            Some("http://localhost:8080".to_string()),
+        )
+        .unwrap();
+        assert_eq!(overridden.api, "anthropic");
+        assert!(overridden.api_key.is_none());
+        assert!(resolve("nope", &configured, None).is_err());
+        assert_eq!(
+            default_model("openrouter", &configured).as_deref(),
+            Some("mistralai/mistral-small-3.1-24b-instruct")
+        );
+        assert_eq!(default_model("work", &configured), None);
+    }
+}
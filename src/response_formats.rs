@@ -14,6 +14,14 @@ impl ToolResponseFormat {
         ToolResponseFormat::Yaml
     }
 
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ToolResponseFormat::Yaml => "yaml",
+            ToolResponseFormat::JsonVariableKeys => "json",
+            ToolResponseFormat::JsonFixedKeys => "json_fixed_key",
+        }
+    }
+
     pub fn from_str(s: &str) -> Result<Self, String> {
         match s.to_lowercase().as_str() {
             "yaml" => Ok(ToolResponseFormat::Yaml),
@@ -195,6 +203,7 @@ pub fn parse_tool_response(
 pub fn response_format(
     tool_response_format: &ToolResponseFormat,
     tool_names: &Vec<serde_json::Value>,
+    tool_args_schemas: &[Value],
 ) -> Option<ResponseFormat> {
     return match tool_response_format {
         ToolResponseFormat::JsonFixedKeys => {
@@ -206,13 +215,7 @@ pub fn response_format(
                         "enum": tool_names
                     },
                     "tool_args": {
-                        "type": "object",
-                        "additionalProperties": {
-                            "anyOf": [
-                                {"type": "string"},
-                                {"type": "number"}
-                            ]
-                        }
+                        "anyOf": tool_args_schemas
                     }
                 },
                 "required": ["tool", "tool_args"],
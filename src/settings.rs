@@ -0,0 +1,507 @@
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value as YamlValue};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::command_tools::CommandTool;
+use crate::mcp::McpServerConfig;
+use crate::providers::ProviderConfig;
+use crate::trust;
+
+pub const PROJECT_CONFIG: &str = ".attotool.yaml";
+const ENV_PREFIX: &str = "ATTOTOOL_";
+
+// Lowest layer; every key here can also be set from the environment.
+const DEFAULTS: &str = "
+model: mistralai/mistral-small-3.1-24b-instruct
+format: yaml
+provider: openrouter
+max_tokens: 2000
+max_tool_calls: 0
+retries: 3
+verbose: false
+tool_call_details: false
+disable_agents_md: false
+yolo: false
+plan: false
+no_shell: false
+no_clarify: false
+pty: false
+no_stream: false
+shell: bash
+dotenv: true
+cache_prompt: true
+";
+
+// Optional keys that can be set from the environment too.
+const OPTIONAL_ENV_KEYS: [&str; 2] = ["base_url", "api"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    Profile(String),
+    Env(String),
+    Cli,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(path) => write!(f, "{}", path.display()),
+            Source::Profile(name) => write!(f, "profile {}", name),
+            Source::Env(var) => write!(f, "env {}", var),
+            Source::Cli => write!(f, "command line"),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct HttpConfig {
+    pub allow_domains: Option<Vec<String>>,
+    pub deny_domains: Option<Vec<String>>,
+    pub max_response_bytes: Option<usize>,
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct WebSearchConfig {
+    pub backend: Option<String>,
+    pub url: String,
+    pub max_results: Option<usize>,
+    pub timeout_secs: Option<u64>,
+}
+
+fn default_instruction_files() -> Vec<String> {
+    vec!["AGENTS.md".to_string()]
+}
+
+fn default_contributing_sections() -> Vec<String> {
+    ["build", "test", "style", "convention", "develop"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+#[derive(Clone, Deserialize)]
+pub struct InstructionsConfig {
+    // Files read from every directory between the repository root and the
+    // files being worked on, e.g. AGENTS.md, .cursorrules, CONTRIBUTING.md.
+    #[serde(default = "default_instruction_files")]
+    pub files: Vec<String>,
+    // Only CONTRIBUTING.md sections whose heading contains one of these
+    // (ignoring case) are used.
+    #[serde(default = "default_contributing_sections")]
+    pub contributing_sections: Vec<String>,
+}
+
+impl Default for InstructionsConfig {
+    fn default() -> Self {
+        InstructionsConfig {
+            files: default_instruction_files(),
+            contributing_sections: default_contributing_sections(),
+        }
+    }
+}
+
+#[derive(Clone, Default, Deserialize)]
+pub struct ToolPolicy {
+    // Tools that are never offered to the model.
+    #[serde(default)]
+    pub disabled: Vec<String>,
+    // Tools that run without an approval prompt, as in --yolo.
+    #[serde(default)]
+    pub auto_approve: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct Settings {
+    pub model: String,
+    pub format: String,
+    pub provider: String,
+    #[serde(default)]
+    pub providers: BTreeMap<String, ProviderConfig>,
+    pub base_url: Option<String>,
+    pub api: Option<String>,
+    pub max_tokens: u32,
+    pub max_tool_calls: u32,
+    pub retries: u32,
+    pub verbose: bool,
+    pub tool_call_details: bool,
+    pub disable_agents_md: bool,
+    pub yolo: bool,
+    pub plan: bool,
+    pub no_shell: bool,
+    pub no_clarify: bool,
+    pub pty: bool,
+    pub no_stream: bool,
+    pub shell: String,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    pub dotenv: bool,
+    // Sequences that end the model's reply.
+    #[serde(default)]
+    pub stop: Vec<String>,
+    // Mark the prompt prefix as cacheable where the API needs it.
+    pub cache_prompt: bool,
+    pub http: Option<HttpConfig>,
+    pub web_search: Option<WebSearchConfig>,
+    #[serde(default)]
+    pub command_tools: Vec<CommandTool>,
+    #[serde(default)]
+    pub mcp_servers: BTreeMap<String, McpServerConfig>,
+    #[serde(default)]
+    pub tools: ToolPolicy,
+    #[serde(default)]
+    pub instructions: InstructionsConfig,
+    // Where each leaf value came from, keyed by dotted path.
+    #[serde(skip)]
+    pub sources: BTreeMap<String, Source>,
+    // Whether the project's own files (config, AGENTS.md, .env) may be used.
+    #[serde(skip)]
+    pub trusted: bool,
+    #[serde(skip)]
+    merged: Mapping,
+}
+
+fn join_path(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+fn record_leaves(
+    value: &YamlValue,
+    path: &str,
+    source: &Source,
+    sources: &mut BTreeMap<String, Source>,
+) {
+    match value {
+        YamlValue::Mapping(map) if !map.is_empty() => {
+            for (key, nested) in map {
+                if let Some(key) = key.as_str() {
+                    let nested_path = join_path(path, key);
+                    record_leaves(nested, &nested_path, source, sources);
+                }
+            }
+        }
+        _ => {
+            sources.insert(path.to_string(), source.clone());
+        }
+    }
+}
+
+// Deep-merges `layer` into `target`: mappings are merged key by key, any
+// other value (lists included) replaces what was there.
+fn apply_layer(
+    target: &mut Mapping,
+    layer: &Mapping,
+    source: &Source,
+    prefix: &str,
+    sources: &mut BTreeMap<String, Source>,
+) {
+    for (key, value) in layer {
+        let Some(name) = key.as_str() else {
+            continue;
+        };
+        let path = join_path(prefix, name);
+        if let (
+            Some(YamlValue::Mapping(existing)),
+            YamlValue::Mapping(nested),
+        ) = (target.get_mut(key), value)
+        {
+            apply_layer(existing, nested, source, &path, sources);
+            continue;
+        }
+        let nested_prefix = format!("{}.", path);
+        sources.retain(|p, _| *p != path && !p.starts_with(&nested_prefix));
+        record_leaves(value, &path, source, sources);
+        target.insert(key.clone(), value.clone());
+    }
+}
+
+fn read_layer(path: &Path) -> Result<Option<Mapping>, String> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Ok(None);
+    };
+    match serde_yaml::from_str::<YamlValue>(&content) {
+        Ok(YamlValue::Mapping(map)) => Ok(Some(map)),
+        Ok(YamlValue::Null) => Ok(None),
+        Ok(_) => Err(format!("{}: expected a mapping", path.display())),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+pub fn user_config_path() -> PathBuf {
+    let home = env::var("HOME").unwrap_or_default();
+    Path::new(&home).join(".config/attotool/config.yaml")
+}
+
+// Loads the settings from every layer. `cli` holds only the options that
+// were given on the command line. The project config of `dir` is skipped
+// unless the project is `trusted`.
+pub fn load(
+    cli: Mapping,
+    profile: Option<String>,
+    trusted: bool,
+    dir: &Path,
+) -> Result<Settings, String> {
+    let mut paths = vec![user_config_path()];
+    if trusted {
+        paths.push(trust::project_file(dir, PROJECT_CONFIG));
+    }
+    let mut files = Vec::new();
+    for path in paths {
+        if let Some(layer) = read_layer(&path)? {
+            files.push((Source::File(path), layer));
+        }
+    }
+    let vars = env::vars().filter(|(k, _)| k.starts_with(ENV_PREFIX)).collect();
+    let mut settings = build(files, vars, cli, profile)?;
+    settings.trusted = trusted;
+    Ok(settings)
+}
+
+fn build(
+    files: Vec<(Source, Mapping)>,
+    vars: Vec<(String, String)>,
+    cli: Mapping,
+    profile: Option<String>,
+) -> Result<Settings, String> {
+    let defaults: Mapping =
+        serde_yaml::from_str(DEFAULTS).expect("invalid built-in defaults");
+    let mut merged = Mapping::new();
+    let mut sources = BTreeMap::new();
+    apply_layer(&mut merged, &defaults, &Source::Default, "", &mut sources);
+    for (source, layer) in &files {
+        apply_layer(&mut merged, layer, source, "", &mut sources);
+    }
+
+    // The profile is picked by --profile, then ATTOTOOL_PROFILE, then a
+    // `profile` key in the config files.
+    let env_profile = format!("{}PROFILE", ENV_PREFIX);
+    let selected = match profile {
+        Some(name) => Some((name, Source::Cli)),
+        None => match vars.iter().find(|(k, _)| *k == env_profile) {
+            Some((var, name)) => Some((name.clone(), Source::Env(var.clone()))),
+            None => merged.get("profile").and_then(|p| p.as_str()).map(|p| {
+                let source =
+                    sources.get("profile").cloned().unwrap_or(Source::Default);
+                (p.to_string(), source)
+            }),
+        },
+    };
+    let profiles = merged.remove("profiles");
+    sources.retain(|p, _| p != "profiles" && !p.starts_with("profiles."));
+    if let Some((name, source)) = selected {
+        let layer = match profiles.as_ref().and_then(|p| p.get(name.as_str())) {
+            Some(YamlValue::Mapping(layer)) => layer.clone(),
+            _ => return Err(format!("unknown profile `{}`", name)),
+        };
+        let mut choice = Mapping::new();
+        choice.insert("profile".into(), name.as_str().into());
+        apply_layer(&mut merged, &choice, &source, "", &mut sources);
+        let source = Source::Profile(name);
+        apply_layer(&mut merged, &layer, &source, "", &mut sources);
+    }
+
+    for (var, value) in &vars {
+        let key = var[ENV_PREFIX.len()..].to_lowercase();
+        if !defaults.contains_key(key.as_str())
+            && !OPTIONAL_ENV_KEYS.contains(&key.as_str())
+        {
+            continue;
+        }
+        // Only keys whose default is a number or a bool are parsed; the
+        // others keep the value as text, so ATTOTOOL_MODEL=123 stays "123".
+        let typed = defaults
+            .get(key.as_str())
+            .is_some_and(|d| d.is_number() || d.is_bool());
+        let parsed = serde_yaml::from_str::<YamlValue>(value)
+            .ok()
+            .filter(|v| typed && (v.is_number() || v.is_bool()))
+            .unwrap_or_else(|| YamlValue::String(value.clone()));
+        let mut layer = Mapping::new();
+        layer.insert(key.into(), parsed);
+        apply_layer(
+            &mut merged,
+            &layer,
+            &Source::Env(var.clone()),
+            "",
+            &mut sources,
+        );
+    }
+    apply_layer(&mut merged, &cli, &Source::Cli, "", &mut sources);
+
+    let mut settings: Settings =
+        serde_yaml::from_value(YamlValue::Mapping(merged.clone()))
+            .map_err(|e| format!("invalid configuration: {}", e))?;
+    settings.sources = sources;
+    settings.merged = merged;
+    Ok(settings)
+}
+
+impl Settings {
+    // Whether `key` was chosen for this run (profile, environment or
+    // command line) rather than coming from the defaults or a config file.
+    pub fn is_explicit(&self, key: &str) -> bool {
+        matches!(
+            self.sources.get(key),
+            Some(Source::Profile(_) | Source::Env(_) | Source::Cli)
+        )
+    }
+
+    // The provider's default model replaces a model from the config files,
+    // but not one picked for this run.
+    pub fn model(&self, provider_model: Option<String>) -> String {
+        match provider_model {
+            Some(model) if !self.is_explicit("model") => model,
+            _ => self.model.clone(),
+        }
+    }
+
+    // Likewise for the provider's stop sequences and prompt caching.
+    pub fn stop(&self, provider_stop: Option<Vec<String>>) -> Vec<String> {
+        match provider_stop {
+            Some(stop) if !self.is_explicit("stop") => stop,
+            _ => self.stop.clone(),
+        }
+    }
+
+    pub fn cache_prompt(&self, provider_cache_prompt: Option<bool>) -> bool {
+        match provider_cache_prompt {
+            Some(cache) if !self.is_explicit("cache_prompt") => cache,
+            _ => self.cache_prompt,
+        }
+    }
+
+    // Every effective value with its source, one per line.
+    pub fn show(&self) -> String {
+        self.sources
+            .iter()
+            .map(|(path, source)| {
+                let mut parts = path.split('.');
+                let first = parts.next().unwrap_or_default();
+                let value = parts
+                    .try_fold(self.merged.get(first), |v, part| {
+                        Some(v.and_then(|v| v.get(part)))
+                    });
+                let rendered = value
+                    .flatten()
+                    .and_then(|v| serde_json::to_string(v).ok())
+                    .unwrap_or_default();
+                format!("{}: {}  # {}", path, rendered, source)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(yaml: &str) -> Mapping {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_layers_profiles_and_sources() {
+        let user = PathBuf::from("user.yaml");
+        let project = PathBuf::from(".attotool.yaml");
+        let files = vec![
+            (
+                Source::File(user.clone()),
+                layer(
+                    "model: user-model\nmax_tokens: 4000\nhttp:\n  timeout_secs: 10\n  allow_domains: [docs.rs]\nprofiles:\n  fast:\n    model: fast-model\n    retries: 1\n",
+                ),
+            ),
+            (
+                Source::File(project.clone()),
+                layer("http:\n  timeout_secs: 20\nno_shell: true\n"),
+            ),
+        ];
+        let vars = vec![
+            ("ATTOTOOL_RETRIES".to_string(), "5".to_string()),
+            ("ATTOTOOL_UNRELATED".to_string(), "x".to_string()),
+        ];
+        let settings =
+            build(files.clone(), vars.clone(), layer("plan: true"), None)
+                .unwrap();
+        assert_eq!(settings.model, "user-model");
+        assert_eq!(settings.max_tokens, 4000);
+        assert_eq!(settings.retries, 5);
+        assert!(settings.plan && settings.no_shell);
+        let http = settings.http.as_ref().unwrap();
+        assert_eq!(http.timeout_secs, Some(20));
+        assert_eq!(http.allow_domains, Some(vec!["docs.rs".to_string()]));
+        assert_eq!(settings.sources["format"], Source::Default);
+        assert_eq!(settings.sources["http.allow_domains"], Source::File(user));
+        assert_eq!(
+            settings.sources["http.timeout_secs"],
+            Source::File(project)
+        );
+        assert_eq!(
+            settings.sources["retries"],
+            Source::Env("ATTOTOOL_RETRIES".to_string())
+        );
+        assert_eq!(settings.sources["plan"], Source::Cli);
+        assert!(!settings.sources.contains_key("profiles.fast.model"));
+        assert_eq!(
+            settings.model(Some("provider-model".to_string())),
+            "provider-model"
+        );
+        assert!(settings.cache_prompt && !settings.cache_prompt(Some(false)));
+        assert_eq!(settings.stop(None), Vec::<String>::new());
+        assert!(
+            settings.show().contains("http.timeout_secs: 20  # .attotool.yaml")
+        );
+
+        let settings = build(
+            files.clone(),
+            vars,
+            Mapping::new(),
+            Some("fast".to_string()),
+        )
+        .unwrap();
+        assert_eq!(settings.model, "fast-model");
+        // The environment still overrides the profile.
+        assert_eq!(settings.retries, 5);
+        assert_eq!(settings.sources["profile"], Source::Cli);
+        assert_eq!(
+            settings.sources["model"],
+            Source::Profile("fast".to_string())
+        );
+        assert_eq!(
+            settings.model(Some("provider-model".to_string())),
+            "fast-model"
+        );
+
+        // Values of text settings are never parsed as YAML.
+        let vars = vec![
+            ("ATTOTOOL_MODEL".to_string(), "123".to_string()),
+            ("ATTOTOOL_SHELL".to_string(), "yes".to_string()),
+            ("ATTOTOOL_BASE_URL".to_string(), "null".to_string()),
+            ("ATTOTOOL_MAX_TOKENS".to_string(), "300".to_string()),
+            ("ATTOTOOL_VERBOSE".to_string(), "true".to_string()),
+        ];
+        let settings =
+            build(files.clone(), vars, Mapping::new(), None).unwrap();
+        assert_eq!(settings.model, "123");
+        assert_eq!(settings.shell, "yes");
+        assert_eq!(settings.base_url.as_deref(), Some("null"));
+        assert_eq!(settings.max_tokens, 300);
+        assert!(settings.verbose);
+
+        assert!(
+            build(files, Vec::new(), Mapping::new(), Some("nope".to_string()))
+                .is_err()
+        );
+    }
+}
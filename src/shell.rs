@@ -0,0 +1,609 @@
+use portable_pty::{
+    Child, CommandBuilder, MasterPty, PtySize, SlavePty, native_pty_system,
+};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::command_tools::shell_quote;
+
+// How long a PTY command may stay silent before we assume it is waiting for
+// input and hand control back to the model.
+const PTY_IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const KILL_KEY: u8 = b'k';
+
+#[derive(Clone)]
+pub struct ShellOptions {
+    pub pty: bool,
+    pub stream: bool,
+    pub shell: String,
+    pub env: BTreeMap<String, String>,
+    // Commands still running after this long are killed. Not applied in a
+    // PTY, where long-running commands hand control back to the model.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for ShellOptions {
+    fn default() -> Self {
+        ShellOptions {
+            pty: false,
+            stream: false,
+            shell: "bash".to_string(),
+            env: BTreeMap::new(),
+            timeout: None,
+        }
+    }
+}
+
+pub enum CommandSpec {
+    // A command line interpreted by the configured shell
+    Shell(String),
+    // A program and its arguments, executed directly without a shell
+    Argv(Vec<String>),
+}
+
+impl CommandSpec {
+    pub fn display(&self) -> String {
+        match self {
+            CommandSpec::Shell(line) => line.clone(),
+            CommandSpec::Argv(argv) => argv
+                .iter()
+                .map(|a| shell_quote(a))
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+
+    fn argv(&self, options: &ShellOptions) -> Vec<String> {
+        match self {
+            CommandSpec::Shell(line) => {
+                vec![options.shell.clone(), "-c".to_string(), line.clone()]
+            }
+            CommandSpec::Argv(argv) => argv.clone(),
+        }
+    }
+}
+
+struct PtySession {
+    child: Box<dyn Child + Send + Sync>,
+    writer: Box<dyn Write + Send>,
+    output: Receiver<Vec<u8>>,
+    stream: bool,
+    _master: Box<dyn MasterPty + Send>,
+    // Held open so the final output of an exiting child is not discarded
+    // by a hangup before the reader gets to it.
+    _slave: Box<dyn SlavePty + Send>,
+}
+
+impl PtySession {
+    // The PTY child leads a session of its own, so its whole process group
+    // is signalled.
+    fn kill(&mut self) {
+        if let Some(pid) = self.child.process_id() {
+            kill_group(pid);
+        }
+        self.child.kill().ok();
+    }
+}
+
+fn kill_group(pid: u32) {
+    unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) };
+}
+
+// Puts the terminal into non-canonical, no-echo mode for the lifetime of a
+// streamed command so a single key press can stop it. Does nothing when stdin
+// is not a terminal.
+struct KillKeyWatcher {
+    original: Option<libc::termios>,
+}
+
+impl KillKeyWatcher {
+    fn start() -> Self {
+        if !io::stdin().is_terminal() {
+            return KillKeyWatcher { original: None };
+        }
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return KillKeyWatcher { original: None };
+        }
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = 0;
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) };
+        print_dimmed(&format!(
+            "(press '{}' to stop the command and return its partial output)",
+            KILL_KEY as char
+        ));
+        KillKeyWatcher {
+            original: Some(original),
+        }
+    }
+
+    fn pressed(&self) -> bool {
+        if self.original.is_none() {
+            return false;
+        }
+        let mut byte = 0u8;
+        while unsafe {
+            libc::read(
+                libc::STDIN_FILENO,
+                &mut byte as *mut u8 as *mut libc::c_void,
+                1,
+            )
+        } == 1
+        {
+            if byte == KILL_KEY {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+impl Drop for KillKeyWatcher {
+    fn drop(&mut self) {
+        if let Some(original) = self.original {
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &original)
+            };
+        }
+    }
+}
+
+fn print_dimmed(text: &str) {
+    let mut stdout = io::stdout();
+    for line in text.lines() {
+        if stdout.is_terminal() {
+            writeln!(stdout, "\x1b[2m│ {}\x1b[0m", line).ok();
+        } else {
+            writeln!(stdout, "│ {}", line).ok();
+        }
+    }
+    stdout.flush().ok();
+}
+
+fn format_output(stdout: &[u8], stderr: &[u8]) -> String {
+    let mut result = String::from_utf8_lossy(stdout).to_string();
+    if !stderr.is_empty() {
+        result.push_str(&format!(
+            "\nStderr: {}",
+            String::from_utf8_lossy(stderr)
+        ));
+    }
+    result
+}
+
+fn spawn_line_reader<R: Read + Send + 'static>(
+    source: R,
+    is_stderr: bool,
+    tx: Sender<(bool, Vec<u8>)>,
+) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(source);
+        loop {
+            let mut line = Vec::new();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    if tx.send((is_stderr, line)).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+pub fn run_command(
+    spec: &CommandSpec,
+    options: &ShellOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let argv = spec.argv(options);
+    let (program, program_args) =
+        argv.split_first().ok_or("argv must not be empty")?;
+    let mut command = Command::new(program);
+    command.args(program_args).envs(&options.env);
+    if !options.stream && options.timeout.is_none() {
+        let output = command
+            .output()
+            .map_err(|e| format!("Failed to execute command: {}", e))?;
+        return Ok(format_output(&output.stdout, &output.stderr));
+    }
+
+    // A process group of its own lets a timeout or the kill key stop the
+    // background jobs of the command too.
+    let mut child = command
+        .process_group(0)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to execute command: {}", e))?;
+    let (tx, rx) = mpsc::channel();
+    if let Some(out) = child.stdout.take() {
+        spawn_line_reader(out, false, tx.clone());
+    }
+    if let Some(err) = child.stderr.take() {
+        spawn_line_reader(err, true, tx);
+    }
+
+    let watcher = options.stream.then(KillKeyWatcher::start);
+    let deadline = options.timeout.map(|t| Instant::now() + t);
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    let mut killed = false;
+    let mut timed_out = false;
+    loop {
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok((is_stderr, line)) => {
+                if options.stream {
+                    print_dimmed(&String::from_utf8_lossy(&line));
+                }
+                if is_stderr {
+                    stderr.extend(line);
+                } else {
+                    stdout.extend(line);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        timed_out = deadline.is_some_and(|d| Instant::now() >= d);
+        killed = watcher.as_ref().is_some_and(|w| w.pressed());
+        if killed || timed_out {
+            kill_group(child.id());
+            // Background processes may keep the pipes open, so take what has
+            // already arrived instead of waiting for EOF.
+            while let Ok((is_stderr, line)) = rx.try_recv() {
+                if is_stderr {
+                    stderr.extend(line);
+                } else {
+                    stdout.extend(line);
+                }
+            }
+            break;
+        }
+    }
+    drop(watcher);
+    child.wait()?;
+
+    let mut result = format_output(&stdout, &stderr);
+    if killed {
+        result.push_str(
+            "\n[Command was stopped by the user; the output above is partial]",
+        );
+    } else if timed_out {
+        result.push_str(&format!(
+            "\n[Command timed out after {} seconds; the output above is partial]",
+            options.timeout.unwrap_or_default().as_secs()
+        ));
+    }
+    Ok(result)
+}
+
+static PTY_SESSION: Mutex<Option<PtySession>> = Mutex::new(None);
+
+pub fn run_in_pty(
+    spec: &CommandSpec,
+    options: &ShellOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let argv = spec.argv(options);
+    if argv.is_empty() {
+        return Err("argv must not be empty".into());
+    }
+    let mut session_slot = PTY_SESSION.lock().unwrap();
+    if let Some(mut previous) = session_slot.take() {
+        previous.kill();
+    }
+
+    let pair = native_pty_system().openpty(PtySize {
+        rows: 40,
+        cols: 120,
+        pixel_width: 0,
+        pixel_height: 0,
+    })?;
+    let mut cmd =
+        CommandBuilder::from_argv(argv.into_iter().map(|a| a.into()).collect());
+    for (key, value) in &options.env {
+        cmd.env(key, value);
+    }
+    cmd.cwd(std::env::current_dir()?);
+    let child = pair.slave.spawn_command(cmd)?;
+
+    let mut reader = pair.master.try_clone_reader()?;
+    let writer = pair.master.take_writer()?;
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if tx.send(buf[..n].to_vec()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    let mut session = PtySession {
+        child,
+        writer,
+        output: rx,
+        stream: options.stream,
+        _master: pair.master,
+        _slave: pair.slave,
+    };
+    let (output, still_running) = collect_pty_output(&mut session);
+    if still_running {
+        *session_slot = Some(session);
+    }
+    Ok(output)
+}
+
+pub fn write_to_pty(input: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut session_slot = PTY_SESSION.lock().unwrap();
+    let mut session = session_slot.take().ok_or(
+        "No interactive command is running. Start one with execute_shell_command first.",
+    )?;
+    if !input.is_empty() {
+        session.writer.write_all(input.as_bytes())?;
+        session.writer.write_all(b"\r")?;
+        session.writer.flush()?;
+    }
+    let (output, still_running) = collect_pty_output(&mut session);
+    if still_running {
+        *session_slot = Some(session);
+    }
+    Ok(output)
+}
+
+// Returns the cleaned output and whether the command is still running.
+fn collect_pty_output(session: &mut PtySession) -> (String, bool) {
+    let watcher = if session.stream {
+        Some(KillKeyWatcher::start())
+    } else {
+        None
+    };
+    let mut raw = Vec::new();
+    let mut killed = false;
+    let mut last_output = Instant::now();
+    let still_running = loop {
+        match session.output.recv_timeout(POLL_INTERVAL) {
+            Ok(chunk) => {
+                if session.stream {
+                    print_dimmed(&strip_terminal_escapes(&chunk));
+                }
+                raw.extend(chunk);
+                last_output = Instant::now();
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if matches!(session.child.try_wait(), Ok(Some(_))) {
+                    while let Ok(chunk) =
+                        session.output.recv_timeout(POLL_INTERVAL)
+                    {
+                        if session.stream {
+                            print_dimmed(&strip_terminal_escapes(&chunk));
+                        }
+                        raw.extend(chunk);
+                    }
+                    break false;
+                }
+                if last_output.elapsed() >= PTY_IDLE_TIMEOUT {
+                    break matches!(session.child.try_wait(), Ok(None));
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break false,
+        }
+        if watcher.as_ref().is_some_and(|w| w.pressed()) {
+            session.kill();
+            killed = true;
+            break false;
+        }
+    };
+    drop(watcher);
+    let mut result = strip_terminal_escapes(&raw);
+    if killed {
+        result.push_str(
+            "\n[Command was stopped by the user; the output above is partial]",
+        );
+    } else if still_running {
+        result.push_str(
+            "\n[The command is still running and may be waiting for input. Use write_to_pty to answer it, or send empty input to keep waiting.]",
+        );
+    } else if let Ok(status) = session.child.wait()
+        && !status.success()
+    {
+        result.push_str(&format!(
+            "\n[Process exited with status {}]",
+            status.exit_code()
+        ));
+    }
+    (result, still_running)
+}
+
+pub fn strip_terminal_escapes(raw: &[u8]) -> String {
+    let mut bytes = Vec::with_capacity(raw.len());
+    let mut i = 0;
+    while i < raw.len() {
+        match raw[i] {
+            0x1b => {
+                i += 1;
+                match raw.get(i) {
+                    // CSI: ESC [ params... final byte in 0x40..=0x7e
+                    Some(b'[') => {
+                        i += 1;
+                        while i < raw.len() && !(0x40..=0x7e).contains(&raw[i])
+                        {
+                            i += 1;
+                        }
+                        i += 1;
+                    }
+                    // OSC: ESC ] ... terminated by BEL or ESC \
+                    Some(b']') => {
+                        i += 1;
+                        while i < raw.len() {
+                            if raw[i] == 0x07 {
+                                i += 1;
+                                break;
+                            }
+                            if raw[i] == 0x1b && raw.get(i + 1) == Some(&b'\\')
+                            {
+                                i += 2;
+                                break;
+                            }
+                            i += 1;
+                        }
+                    }
+                    // Character set selection: ESC ( B and friends
+                    Some(b'(') | Some(b')') => i += 2,
+                    Some(_) => i += 1,
+                    None => {}
+                }
+            }
+            b'\n' | b'\t' | b'\r' => {
+                bytes.push(raw[i]);
+                i += 1;
+            }
+            c if c < 0x20 || c == 0x7f => i += 1,
+            c => {
+                bytes.push(c);
+                i += 1;
+            }
+        }
+    }
+    let text = String::from_utf8_lossy(&bytes);
+    // A lone carriage return redraws the line (progress bars, spinners), so
+    // only the last redraw of each line is kept.
+    text.split('\n')
+        .map(|line| {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            line.rsplit('\r').next().unwrap_or(line)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_terminal_escapes_colors_and_crlf() {
+        let raw = b"\x1b[1;32mok\x1b[0m\r\nnext line\r\n";
+        assert_eq!(strip_terminal_escapes(raw), "ok\nnext line\n");
+    }
+
+    #[test]
+    fn test_strip_terminal_escapes_osc_and_progress() {
+        let raw = b"\x1b]0;window title\x07 10%\r 50%\r100%\ndone";
+        assert_eq!(strip_terminal_escapes(raw), "100%\ndone");
+    }
+
+    #[test]
+    fn test_strip_terminal_escapes_charset_and_bell() {
+        let raw = b"\x1b(Bplain\x07 text";
+        assert_eq!(strip_terminal_escapes(raw), "plain text");
+    }
+
+    #[test]
+    fn test_run_command_streamed_captures_stdout_and_stderr() {
+        let options = ShellOptions {
+            stream: true,
+            ..Default::default()
+        };
+        let spec = CommandSpec::Shell("echo out; echo err 1>&2".to_string());
+        let output = run_command(&spec, &options).unwrap();
+        assert_eq!(output, "out\n\nStderr: err\n");
+    }
+
+    #[test]
+    fn test_run_command_kills_command_after_timeout() {
+        let options = ShellOptions {
+            timeout: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let spec =
+            CommandSpec::Shell("echo started; exec sleep 10".to_string());
+        let start = Instant::now();
+        let output = run_command(&spec, &options).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(
+            output,
+            "started\n\n[Command timed out after 1 seconds; the output above is partial]"
+        );
+
+        // Background jobs of the command are killed along with it.
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("marker");
+        let spec = CommandSpec::Shell(format!(
+            "(sleep 2; touch '{}') & wait",
+            marker.display()
+        ));
+        run_command(&spec, &options).unwrap();
+        thread::sleep(Duration::from_secs(2));
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn test_run_command_argv_skips_shell_and_applies_env() {
+        let mut options = ShellOptions::default();
+        options
+            .env
+            .insert("ATTOTOOL_TEST_VAR".to_string(), "from-config".to_string());
+        let spec = CommandSpec::Argv(vec![
+            "printenv".to_string(),
+            "ATTOTOOL_TEST_VAR".to_string(),
+        ]);
+        assert_eq!(run_command(&spec, &options).unwrap(), "from-config\n");
+
+        let spec = CommandSpec::Argv(vec![
+            "echo".to_string(),
+            "$HOME; it's literal".to_string(),
+        ]);
+        assert_eq!(
+            run_command(&spec, &options).unwrap(),
+            "$HOME; it's literal\n"
+        );
+    }
+
+    #[test]
+    fn test_command_spec_display_quotes_argv() {
+        let spec = CommandSpec::Argv(vec![
+            "git".to_string(),
+            "commit".to_string(),
+            "-m".to_string(),
+            "it's done".to_string(),
+        ]);
+        assert_eq!(spec.display(), "git commit -m 'it'\\''s done'");
+        let spec = CommandSpec::Argv(vec![
+            "echo".to_string(),
+            "$HOME;rm".to_string(),
+            "*".to_string(),
+            "".to_string(),
+        ]);
+        assert_eq!(spec.display(), "echo '$HOME;rm' '*' ''");
+    }
+
+    #[test]
+    fn test_run_in_pty_answers_prompt() {
+        let options = ShellOptions::default();
+        let spec = CommandSpec::Shell(
+            "read -p 'Name? ' name; echo \"hello $name\"".to_string(),
+        );
+        let output = run_in_pty(&spec, &options).unwrap();
+        assert!(output.contains("Name?"));
+        assert!(output.contains("still running"));
+        let output = write_to_pty("attotool").unwrap();
+        assert!(output.contains("hello attotool"), "{:?}", output);
+        assert!(!output.contains("still running"));
+    }
+}
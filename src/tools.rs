@@ -1,56 +1,149 @@
-use serde_json::Value;
-use std::fs;
-use std::io::{self, Write};
-use std::process;
+use serde_json::{Map, Value, json};
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::command_tools::CommandTool;
+
+use crate::edits::{self, LineEdit, TextFormat};
+use crate::file_read;
+use crate::file_search::{self, FindSort, ListOptions, SearchOptions};
+use crate::file_versions;
+use crate::http::{self, HttpOptions, HttpRequest};
+use crate::mcp::McpTool;
+use crate::patch;
+use crate::settings::ToolPolicy;
+use crate::shell::{self, CommandSpec, ShellOptions};
+use crate::transaction;
+use crate::web_search::{self, WebSearchOptions};
+
+#[derive(Clone, Default)]
+pub struct ToolConfig {
+    pub shell: ShellOptions,
+    pub http: HttpOptions,
+    // Only set when a search backend is configured.
+    pub web_search: Option<WebSearchOptions>,
+    pub command_tools: Vec<CommandTool>,
+    pub mcp_tools: Vec<McpTool>,
+    pub policy: ToolPolicy,
+}
+
+impl ToolConfig {
+    // Tools listed under `tools.auto_approve` run as in --yolo.
+    pub fn yolo_for(&self, tool: &str, yolo: bool) -> bool {
+        yolo || self.policy.auto_approve.iter().any(|t| t == tool)
+    }
+}
 
 #[derive(Clone)]
 pub enum Tool {
-    ExecuteShellCommand,
+    ExecuteShellCommand(ShellOptions),
+    WriteToPty,
     ReadFile,
     WriteFile,
     ReadLines,
     WriteLines,
+    ReplaceInFile,
+    ApplyPatch,
+    BeginEdit,
+    CommitEdit,
+    AbortEdit,
     ListFiles,
+    SearchFiles,
+    FindFiles,
+    HttpRequest(HttpOptions),
+    WebSearch(WebSearchOptions),
     FinishTask,
     FinishPlanning,
     AskForClarification,
     DescribeToUser,
+    Command(CommandTool, ShellOptions),
+    Mcp(McpTool),
 }
 
 impl Tool {
     pub fn name(&self) -> &str {
         match self {
-            Tool::ExecuteShellCommand => "execute_shell_command",
+            Tool::ExecuteShellCommand(_) => "execute_shell_command",
+            Tool::WriteToPty => "write_to_pty",
             Tool::ReadFile => "read_file",
             Tool::WriteFile => "write_file",
             Tool::ReadLines => "read_lines",
             Tool::WriteLines => "write_lines",
+            Tool::ReplaceInFile => "replace_in_file",
+            Tool::ApplyPatch => "apply_patch",
+            Tool::BeginEdit => "begin_edit",
+            Tool::CommitEdit => "commit_edit",
+            Tool::AbortEdit => "abort_edit",
             Tool::ListFiles => "list_files",
+            Tool::SearchFiles => "search_files",
+            Tool::FindFiles => "find_files",
+            Tool::HttpRequest(_) => "http_request",
+            Tool::WebSearch(_) => "web_search",
             Tool::FinishTask => "finish_task",
             Tool::FinishPlanning => "finish_planning",
             Tool::AskForClarification => "ask_for_clarification",
             Tool::DescribeToUser => "describe_to_user",
+            Tool::Command(tool, _) => &tool.name,
+            Tool::Mcp(tool) => &tool.name,
         }
     }
 
     pub fn description(&self) -> &str {
         match self {
-            Tool::ExecuteShellCommand => {
+            Tool::ExecuteShellCommand(_) => {
                 // not mentioning ls, cat for now
-                "Executes a command with arguments on the bash shell - includes common tools like curl, mkdir. Using 'sed' is not allowed unless the user explicitly requests it"
+                "Executes a command with arguments on the shell - includes common tools like curl, mkdir. Alternatively, pass argv (program followed by its arguments) instead of command and args to run it directly without a shell, which avoids quoting problems. Using 'sed' is not allowed unless the user explicitly requests it"
+            }
+            Tool::WriteToPty => {
+                "Sends a line of input (followed by Enter) to the interactive command still running from execute_shell_command, e.g. to answer a prompt. Empty input waits for more output"
+            }
+            Tool::ReadFile => {
+                "Reads a file on the local filesystem. Lines are prefixed with their line numbers. At most 2000 lines are returned per call; use offset (first line, 1-based) and limit to page through larger files. Binary files are not shown"
             }
-            Tool::ReadFile => "Reads a file on the local filesystem",
             Tool::WriteFile => {
-                "Writes a file on the local filesystem. write_file should not be used to replace only small parts of a file; for that, use write_lines"
+                "Writes a file on the local filesystem. write_file should not be used to replace only small parts of a file; for that, use replace_in_file"
             }
             Tool::ReadLines => {
-                "Reads specific lines from a file between start_line and end_line"
+                "Reads specific lines from a file between start_line and end_line, prefixed with their line numbers"
             }
             Tool::WriteLines => {
-                "Writes content to specific lines in a file between start_line and end_line. write_lines content argument must be on a single line, double-quoted, with newlines escaped with \\n. write_lines should be preferred over write_file when a small, targeted change is desired"
+                "Edits specific lines of a file. mode is replace (default; replaces start_line to end_line with content), insert_before (inserts content before start_line), append (adds content at the end of the file) or delete (removes start_line to end_line). write_lines content argument must be on a single line, double-quoted, with newlines escaped with \\n. Line endings, encoding and the final newline of the file are preserved"
+            }
+            Tool::ReplaceInFile => {
+                "Replaces an exact occurrence of old_string in a file with new_string. old_string must match the file exactly, including whitespace and indentation, and must be unique unless replace_all is true; include enough surrounding lines to make it unique. This is the preferred way to make targeted edits"
+            }
+            Tool::ApplyPatch => {
+                "Applies a patch that can add, update and delete several files at once; either every file is changed or none is. patch is either a unified diff (as produced by git diff) or this simplified format: '*** Begin Patch' then for each file '*** Add File: path' followed by lines prefixed with +, '*** Delete File: path', or '*** Update File: path' followed by hunks starting with @@ whose lines are prefixed with ' ' (context), - (remove) or + (add), ending with '*** End Patch'. Include a few unchanged context lines around each change"
+            }
+            Tool::BeginEdit => {
+                "Starts a multi-file edit. Until commit_edit or abort_edit, write_file, write_lines, replace_in_file and apply_patch only stage their changes, and later edits to the same file build on the staged version (read_file still shows the file on disk). Use this when several files must change together"
+            }
+            Tool::CommitEdit => {
+                "Applies all changes staged since begin_edit at once, after the user approves the combined diff; either every file is changed or none is"
+            }
+            Tool::AbortEdit => {
+                "Discards all changes staged since begin_edit without touching any file"
             }
             Tool::ListFiles => {
-                "Lists the contents (files and directories) of a given directory"
+                "Lists the files and directories under a directory, skipping anything ignored by .gitignore. Prefer this over running ls through the shell. depth limits recursion (default 2), tree renders an indented tree, glob keeps only matching files (e.g. '*.rs'), sizes shows file sizes, max_entries caps the output (default 200)"
+            }
+            Tool::SearchFiles => {
+                "Searches file contents under path (a directory or a single file) for a regex pattern, skipping files ignored by .gitignore and binary files. Prefer this over grep through the shell. literal treats the pattern as plain text, case_insensitive ignores case, include/exclude are globs on file paths (e.g. '*.rs', 'tests/**'), context adds lines around each match, max_results caps the matching lines shown (default 100). Ends with match counts per file"
+            }
+            Tool::FindFiles => {
+                "Finds files by name with a glob pattern (e.g. '**/*.rs', '**/Cargo.toml', or '*.md' to match file names anywhere) under path, skipping files ignored by .gitignore. Prefer this over find through the shell. sort is 'name' (default) or 'modified' (most recently modified first), max_results caps the output (default 100)"
+            }
+            Tool::HttpRequest(options) if options.read_only => {
+                "Fetches a URL with a GET or HEAD request. Prefer this over curl or wget through the shell. HTML responses are converted to readable text unless raw is true; large responses are truncated. headers is a list of 'Name: value' strings, timeout is in seconds"
+            }
+            Tool::HttpRequest(_) => {
+                "Sends an HTTP request and returns the status, main headers and body. Prefer this over curl or wget through the shell. method defaults to GET; headers is a list of 'Name: value' strings; body is sent as-is; timeout is in seconds. HTML responses are converted to readable text unless raw is true; large responses are truncated. Methods other than GET and HEAD require user approval"
+            }
+            Tool::WebSearch(_) => {
+                "Searches the web and returns a numbered list of results with title, URL and snippet. Prefer this over fetching search engine pages with http_request or curl; fetch promising results with http_request. max_results caps the number of results (default from config)"
             }
             Tool::FinishTask => {
                 "Marks the assigned task as completed, with a completion message"
@@ -64,16 +157,30 @@ impl Tool {
             Tool::DescribeToUser => {
                 "Provides a description or response to the user"
             }
+            Tool::Command(tool, _) => &tool.description,
+            Tool::Mcp(tool) => &tool.description,
         }
     }
 
     pub fn parameters(&self) -> Vec<(String, String)> {
         match self {
-            Tool::ExecuteShellCommand => vec![
+            Tool::ExecuteShellCommand(_) => vec![
                 ("command".to_string(), "string".to_string()),
                 ("args".to_string(), "string".to_string()),
+                (
+                    "argv".to_string(),
+                    "array of strings (optional, replaces command and args)"
+                        .to_string(),
+                ),
+            ],
+            Tool::WriteToPty => {
+                vec![("input".to_string(), "string".to_string())]
+            }
+            Tool::ReadFile => vec![
+                ("path".to_string(), "string".to_string()),
+                ("offset".to_string(), "integer (optional)".to_string()),
+                ("limit".to_string(), "integer (optional)".to_string()),
             ],
-            Tool::ReadFile => vec![("path".to_string(), "string".to_string())],
             Tool::WriteFile => vec![
                 ("path".to_string(), "string".to_string()),
                 ("content".to_string(), "string".to_string()),
@@ -88,8 +195,60 @@ impl Tool {
                 ("start_line".to_string(), "integer".to_string()),
                 ("end_line".to_string(), "integer".to_string()),
                 ("content".to_string(), "string".to_string()),
+                ("mode".to_string(), "string (optional)".to_string()),
+            ],
+            Tool::ReplaceInFile => vec![
+                ("path".to_string(), "string".to_string()),
+                ("old_string".to_string(), "string".to_string()),
+                ("new_string".to_string(), "string".to_string()),
+                ("replace_all".to_string(), "boolean (optional)".to_string()),
+            ],
+            Tool::ApplyPatch => {
+                vec![("patch".to_string(), "string".to_string())]
+            }
+            Tool::BeginEdit | Tool::CommitEdit | Tool::AbortEdit => vec![],
+            Tool::ListFiles => vec![
+                ("path".to_string(), "string".to_string()),
+                ("depth".to_string(), "integer (optional)".to_string()),
+                ("tree".to_string(), "boolean (optional)".to_string()),
+                ("glob".to_string(), "string (optional)".to_string()),
+                ("sizes".to_string(), "boolean (optional)".to_string()),
+                ("max_entries".to_string(), "integer (optional)".to_string()),
+            ],
+            Tool::SearchFiles => vec![
+                ("pattern".to_string(), "string".to_string()),
+                ("path".to_string(), "string (optional)".to_string()),
+                ("literal".to_string(), "boolean (optional)".to_string()),
+                (
+                    "case_insensitive".to_string(),
+                    "boolean (optional)".to_string(),
+                ),
+                ("include".to_string(), "string (optional)".to_string()),
+                ("exclude".to_string(), "string (optional)".to_string()),
+                ("context".to_string(), "integer (optional)".to_string()),
+                ("max_results".to_string(), "integer (optional)".to_string()),
+            ],
+            Tool::FindFiles => vec![
+                ("pattern".to_string(), "string".to_string()),
+                ("path".to_string(), "string (optional)".to_string()),
+                ("sort".to_string(), "string (optional)".to_string()),
+                ("max_results".to_string(), "integer (optional)".to_string()),
+            ],
+            Tool::HttpRequest(_) => vec![
+                ("url".to_string(), "string".to_string()),
+                ("method".to_string(), "string (optional)".to_string()),
+                (
+                    "headers".to_string(),
+                    "array of strings (optional)".to_string(),
+                ),
+                ("body".to_string(), "string (optional)".to_string()),
+                ("timeout".to_string(), "integer (optional)".to_string()),
+                ("raw".to_string(), "boolean (optional)".to_string()),
+            ],
+            Tool::WebSearch(_) => vec![
+                ("query".to_string(), "string".to_string()),
+                ("max_results".to_string(), "integer (optional)".to_string()),
             ],
-            Tool::ListFiles => vec![("path".to_string(), "string".to_string())],
             Tool::FinishTask => {
                 vec![("message".to_string(), "string".to_string())]
             }
@@ -102,6 +261,8 @@ impl Tool {
             Tool::DescribeToUser => {
                 vec![("description".to_string(), "string".to_string())]
             }
+            Tool::Command(tool, _) => tool.parameters(),
+            Tool::Mcp(tool) => tool.parameters(),
         }
     }
 
@@ -112,14 +273,32 @@ impl Tool {
         yolo: bool,
     ) -> Result<String, Box<dyn std::error::Error>> {
         match self {
-            Tool::ExecuteShellCommand => {
-                execute_shell_command(args, verbose, yolo).await
+            Tool::ExecuteShellCommand(options) => {
+                execute_shell_command(args, verbose, yolo, options).await
             }
+            Tool::WriteToPty => execute_write_to_pty(args, verbose, yolo).await,
             Tool::ReadFile => execute_read_file(args, verbose, yolo).await,
             Tool::WriteFile => execute_write_file(args, verbose, yolo).await,
             Tool::ReadLines => execute_read_lines(args, verbose, yolo).await,
             Tool::WriteLines => execute_write_lines(args, verbose, yolo).await,
+            Tool::ReplaceInFile => {
+                execute_replace_in_file(args, verbose, yolo).await
+            }
+            Tool::ApplyPatch => execute_apply_patch(args, verbose, yolo).await,
+            Tool::BeginEdit => execute_begin_edit(args, verbose, yolo).await,
+            Tool::CommitEdit => execute_commit_edit(args, verbose, yolo).await,
+            Tool::AbortEdit => execute_abort_edit(args, verbose, yolo).await,
             Tool::ListFiles => execute_list_files(args, verbose, yolo).await,
+            Tool::SearchFiles => {
+                execute_search_files(args, verbose, yolo).await
+            }
+            Tool::FindFiles => execute_find_files(args, verbose, yolo).await,
+            Tool::HttpRequest(options) => {
+                execute_http_request(args, verbose, yolo, options).await
+            }
+            Tool::WebSearch(options) => {
+                execute_web_search(args, verbose, yolo, options).await
+            }
             Tool::FinishTask => execute_finish_task(args, verbose, yolo).await,
             Tool::FinishPlanning => {
                 execute_finish_planning(args, verbose, yolo).await
@@ -130,9 +309,46 @@ impl Tool {
             Tool::DescribeToUser => {
                 execute_describe_to_user(args, verbose, yolo).await
             }
+            Tool::Command(tool, options) => {
+                execute_command_tool(args, verbose, yolo, tool, options).await
+            }
+            Tool::Mcp(tool) => {
+                execute_mcp_tool(args, verbose, yolo, tool).await
+            }
         }
     }
 
+    // JSON schema of the tool arguments, used by the json_fixed_key format.
+    pub fn args_schema(&self) -> Value {
+        match self {
+            Tool::Command(tool, _) => return tool.input_schema(),
+            Tool::Mcp(tool) => return tool.input_schema.clone(),
+            _ => {}
+        }
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+        for (name, kind) in self.parameters() {
+            let schema = match kind.split(" (").next().unwrap_or("") {
+                "integer" => json!({"type": "integer"}),
+                "boolean" => json!({"type": "boolean"}),
+                "array of strings" => {
+                    json!({"type": "array", "items": {"type": "string"}})
+                }
+                _ => json!({"type": "string"}),
+            };
+            if !kind.contains("(optional") {
+                required.push(Value::String(name.clone()));
+            }
+            properties.insert(name, schema);
+        }
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+            "additionalProperties": false
+        })
+    }
+
     pub fn format(&self) -> String {
         let params = self
             .parameters()
@@ -144,7 +360,30 @@ impl Tool {
     }
 }
 
+// Set in MCP server mode, where stdin and stdout carry the protocol.
+static APPROVALS_ON_TTY: AtomicBool = AtomicBool::new(false);
+
+pub fn prompt_approvals_on_tty() {
+    APPROVALS_ON_TTY.store(true, Ordering::Relaxed);
+}
+
+fn prompt_approval_on_tty(prompt: &str) -> bool {
+    let tty = OpenOptions::new().read(true).write(true).open("/dev/tty");
+    let Ok(mut tty) = tty else {
+        eprintln!("{}\nNo terminal to ask for approval; declining.", prompt);
+        return false;
+    };
+    writeln!(tty, "{}", prompt).ok();
+    let mut input = String::new();
+    BufReader::new(&tty).read_line(&mut input).ok();
+    let input = input.trim().to_lowercase();
+    input.is_empty() || input == "y"
+}
+
 fn prompt_approval(prompt: &str, _verbose: bool) -> bool {
+    if APPROVALS_ON_TTY.load(Ordering::Relaxed) {
+        return prompt_approval_on_tty(prompt);
+    }
     println!("{}", prompt);
     io::stdout().flush().unwrap();
     let mut input = String::new();
@@ -156,13 +395,44 @@ fn prompt_approval(prompt: &str, _verbose: bool) -> bool {
     input == "y"
 }
 
+fn arg_usize(args: &Value, key: &str, default: usize) -> usize {
+    match &args[key] {
+        Value::Number(n) => n.as_u64().map(|n| n as usize).unwrap_or(default),
+        Value::String(s) => s.trim().parse().unwrap_or(default),
+        _ => default,
+    }
+}
+
+fn arg_bool(args: &Value, key: &str, default: bool) -> bool {
+    match &args[key] {
+        Value::Bool(b) => *b,
+        Value::String(s) => match s.trim().to_lowercase().as_str() {
+            "true" | "yes" | "1" => true,
+            "false" | "no" | "0" => false,
+            _ => default,
+        },
+        _ => default,
+    }
+}
+
+fn arg_path<'a>(args: &'a Value, key: &str) -> &'a str {
+    match args[key].as_str() {
+        Some(path) if !path.trim().is_empty() => path,
+        _ => ".",
+    }
+}
+
 fn truncate_content(content: &str) -> String {
-    if content.len() <= 250 {
-        content.to_string()
-    } else {
-        let truncated = &content[..250];
-        let remaining = content.len() - 250;
-        format!("{}[... and {} more characters]", truncated, remaining)
+    match content.char_indices().nth(250) {
+        None => content.to_string(),
+        Some((end, _)) => {
+            let remaining = content[end..].chars().count();
+            format!(
+                "{}[... and {} more characters]",
+                &content[..end],
+                remaining
+            )
+        }
     }
 }
 
@@ -170,14 +440,28 @@ async fn execute_shell_command(
     args: Value,
     verbose: bool,
     yolo: bool,
+    options: &ShellOptions,
 ) -> Result<String, Box<dyn std::error::Error>> {
-    let command = args["command"].as_str().unwrap_or("");
-    let args_str = args["args"].as_str().unwrap_or("");
+    let spec = match args["argv"].as_array() {
+        Some(argv) if !argv.is_empty() => CommandSpec::Argv(
+            argv.iter()
+                .map(|a| match a {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                })
+                .collect(),
+        ),
+        _ => {
+            let command = args["command"].as_str().unwrap_or("");
+            let args_str = args["args"].as_str().unwrap_or("");
+            CommandSpec::Shell(format!("{} {}", command, args_str))
+        }
+    };
     if !yolo
         && !prompt_approval(
             &format!(
-                "Do you want to run this command: `{} {}` ? (Y/n): ",
-                command, args_str
+                "Do you want to run this command: `{}` ? (Y/n): ",
+                spec.display()
             ),
             verbose,
         )
@@ -186,24 +470,87 @@ async fn execute_shell_command(
             "Command execution request declined by the user.".to_string()
         );
     }
-    let output = match process::Command::new("bash")
-        .arg("-c")
-        .arg(format!("{} {}", command, args_str))
-        .output()
+    if options.pty {
+        return shell::run_in_pty(&spec, options);
+    }
+    shell::run_command(&spec, options)
+}
+
+async fn execute_write_to_pty(
+    args: Value,
+    verbose: bool,
+    yolo: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let input = args["input"].as_str().unwrap_or("");
+    if !input.is_empty()
+        && !yolo
+        && !prompt_approval(
+            &format!(
+                "Do you want to send input `{}` to the running command? (Y/n): ",
+                input
+            ),
+            verbose,
+        )
     {
-        Ok(output) => output,
-        Err(e) => {
-            return Err(format!("Failed to execute command: {}", e).into());
-        }
+        return Ok("Input request declined by the user.".to_string());
+    }
+    shell::write_to_pty(input)
+}
+
+fn format_page(
+    page: Result<file_read::Page, String>,
+    next_page: impl Fn(usize) -> String,
+) -> String {
+    let page = match page {
+        Ok(page) => page,
+        Err(e) => return e,
     };
-    let mut result = format!("{}", String::from_utf8_lossy(&output.stdout));
-    if !output.stderr.is_empty() {
-        result.push_str(&format!(
-            "\nStderr: {}",
-            String::from_utf8_lossy(&output.stderr)
+    let mut output = String::new();
+    if let Some(note) = &page.encoding_note {
+        output.push_str(&format!("[Note: {}]\n", note));
+    }
+    if page.total == 0 {
+        output.push_str("[The file is empty]");
+        return output;
+    }
+    output.push_str(&page.text);
+    if page.has_more() {
+        output.push_str(&format!(
+            "\n[Showing lines {}-{} of {}. {}]",
+            page.first,
+            page.last,
+            page.total,
+            next_page(page.last + 1)
         ));
     }
-    Ok(result)
+    output
+}
+
+// Writing over a file the model has not seen in its current state is refused
+// in yolo mode; otherwise the returned warning is shown in the approval prompt.
+fn check_stale_write(path: &str, yolo: bool) -> Result<String, String> {
+    if transaction::is_staged(Path::new(path)) {
+        return Ok(String::new());
+    }
+    match file_versions::stale_warning(path) {
+        None => Ok(String::new()),
+        Some(warning) if yolo => Err(format!(
+            "Error: {} The file was not written. Read it again with read_file and retry.",
+            warning
+        )),
+        Some(warning) => Ok(format!("Warning: {}\n", warning)),
+    }
+}
+
+fn stage_edit(path: &str, updated: Option<String>, warning: String) -> String {
+    let warning = Some(warning).filter(|w| !w.is_empty());
+    match transaction::stage(Path::new(path), updated, warning) {
+        Ok(count) => format!(
+            "Staged the change to `{}`; {} file(s) have staged changes. Call commit_edit to apply them or abort_edit to discard them.",
+            path, count
+        ),
+        Err(e) => format!("Error staging change: {}", e),
+    }
 }
 
 async fn execute_read_file(
@@ -212,10 +559,15 @@ async fn execute_read_file(
     _yolo: bool,
 ) -> Result<String, Box<dyn std::error::Error>> {
     let path = args["path"].as_str().unwrap_or("");
-    match fs::read_to_string(path) {
-        Ok(content) => Ok(content),
-        Err(e) => Ok(format!("Error reading file: {}", e)),
+    let offset = arg_usize(&args, "offset", 1);
+    let limit = arg_usize(&args, "limit", file_read::DEFAULT_LINE_LIMIT);
+    let page = file_read::read_page(Path::new(path), offset, limit);
+    if page.is_ok() {
+        file_versions::record(Path::new(path));
     }
+    Ok(format_page(page, |next| {
+        format!("To read more, call read_file with offset {}", next)
+    }))
 }
 
 async fn execute_write_file(
@@ -226,19 +578,40 @@ async fn execute_write_file(
     let path = args["path"].as_str().unwrap_or("");
     let content = args["content"].as_str().unwrap_or("");
     let truncated_content = truncate_content(content);
+    let warning = match check_stale_write(path, yolo) {
+        Ok(warning) => warning,
+        Err(rejection) => return Ok(rejection),
+    };
+    // Keep the BOM and line endings of a file being overwritten.
+    let format = match transaction::read_current(Path::new(path)) {
+        Ok(raw) => TextFormat {
+            final_newline: content.ends_with('\n'),
+            ..TextFormat::detect(&raw)
+        },
+        Err(_) => TextFormat {
+            final_newline: content.ends_with('\n'),
+            ..TextFormat::default()
+        },
+    };
+    if transaction::is_open() {
+        return Ok(stage_edit(path, Some(format.render(content)), warning));
+    }
     if !yolo
         && !prompt_approval(
             &format!(
-                "Do you want to write contents `{}` to file `{}`? (Y/n): ",
-                truncated_content, path
+                "{}Do you want to write contents `{}` to file `{}`? (Y/n): ",
+                warning, truncated_content, path
             ),
             verbose,
         )
     {
         return Ok("File write request declined by the user.".to_string());
     }
-    match fs::write(path, content) {
-        Ok(_) => Ok("File written successfully".to_string()),
+    match edits::write_atomic(Path::new(path), &format.render(content)) {
+        Ok(_) => {
+            file_versions::record(Path::new(path));
+            Ok("File written successfully".to_string())
+        }
         Err(e) => Ok(format!("Error writing file: {}", e)),
     }
 }
@@ -249,23 +622,22 @@ async fn execute_read_lines(
     _yolo: bool,
 ) -> Result<String, Box<dyn std::error::Error>> {
     let path = args["path"].as_str().unwrap_or("");
-    let start_line = args["start_line"].as_u64().unwrap_or(1) as usize;
-    let end_line = args["end_line"].as_u64().unwrap_or(1) as usize;
+    let start_line = arg_usize(&args, "start_line", 1);
+    let end_line = arg_usize(&args, "end_line", start_line);
     if start_line == 0 || end_line == 0 || start_line > end_line {
         return Ok("Invalid line range".to_string());
     }
-    match fs::read_to_string(path) {
-        Ok(content) => {
-            let lines: Vec<&str> = content.lines().collect();
-            if start_line > lines.len() {
-                return Ok("Start line out of bounds".to_string());
-            }
-            let end = std::cmp::min(end_line, lines.len());
-            let selected = &lines[start_line - 1..end];
-            Ok(selected.join("\n"))
-        }
-        Err(e) => Ok(format!("Error reading file: {}", e)),
+    let page = file_read::read_page(
+        Path::new(path),
+        start_line,
+        end_line - start_line + 1,
+    );
+    if page.is_ok() {
+        file_versions::record(Path::new(path));
     }
+    Ok(format_page(page, |next| {
+        format!("To read more, call read_lines with start_line {}", next)
+    }))
 }
 
 async fn execute_write_lines(
@@ -274,44 +646,280 @@ async fn execute_write_lines(
     yolo: bool,
 ) -> Result<String, Box<dyn std::error::Error>> {
     let path = args["path"].as_str().unwrap_or("");
-    let start_line = args["start_line"].as_u64().unwrap_or(1) as usize;
-    let end_line = args["end_line"].as_u64().unwrap_or(1) as usize;
+    let start_line = arg_usize(&args, "start_line", 1);
+    let end_line = arg_usize(&args, "end_line", start_line);
     let content = args["content"].as_str().unwrap_or("");
-    if start_line == 0 || end_line == 0 || start_line > end_line {
-        return Ok("Invalid line range".to_string());
+    let edit = match args["mode"].as_str().unwrap_or("replace") {
+        "replace" => LineEdit::Replace {
+            start: start_line,
+            end: end_line,
+        },
+        "insert_before" => LineEdit::InsertBefore { line: start_line },
+        "append" => LineEdit::Append,
+        "delete" => LineEdit::Delete {
+            start: start_line,
+            end: end_line,
+        },
+        other => {
+            return Ok(format!(
+                "Unknown mode `{}`; use replace, insert_before, append or delete",
+                other
+            ));
+        }
+    };
+    let raw = match transaction::read_current(Path::new(path)) {
+        Ok(raw) => raw,
+        Err(e) => return Ok(format!("Error reading file: {}", e)),
+    };
+    let warning = match check_stale_write(path, yolo) {
+        Ok(warning) => warning,
+        Err(rejection) => return Ok(rejection),
+    };
+    let (existing, format) = TextFormat::normalize(&raw);
+    let (updated, first, last) =
+        match edits::apply_line_edit(&existing, &edit, content) {
+            Ok(result) => result,
+            Err(e) => {
+                return Ok(format!("Error: {}. No changes were made.", e));
+            }
+        };
+    if transaction::is_open() {
+        return Ok(format!(
+            "{} The edited region will read:\n{}",
+            stage_edit(path, Some(format.render(&updated)), warning),
+            edits::numbered_snippet(&updated, first, last, 3)
+        ));
     }
-    let truncated_content = truncate_content(content);
     if !yolo
         && !prompt_approval(
             &format!(
-                "Do you want to write contents `{}` to lines {} to {} in file `{}`? (Y/n): ",
-                truncated_content, start_line, end_line, path
+                "{}{}Do you want to apply this change to file `{}`? (Y/n): ",
+                edits::unified_diff(path, &existing, &updated),
+                warning,
+                path
             ),
             verbose,
         )
     {
         return Ok("File write request declined by the user.".to_string());
     }
-    match fs::read_to_string(path) {
-        Ok(existing) => {
-            let mut lines: Vec<String> =
-                existing.lines().map(|s| s.to_string()).collect();
-            if start_line > lines.len() + 1 {
-                return Ok("Start line out of bounds".to_string());
-            }
-            let new_lines: Vec<String> =
-                content.lines().map(|s| s.to_string()).collect();
-            lines.splice(
-                start_line - 1..std::cmp::min(end_line, lines.len()),
-                new_lines,
-            );
-            let new_content = lines.join("\n");
-            match fs::write(path, new_content) {
-                Ok(_) => Ok("Lines written successfully".to_string()),
-                Err(e) => Ok(format!("Error writing file: {}", e)),
+    if let Err(e) =
+        edits::write_atomic(Path::new(path), &format.render(&updated))
+    {
+        return Ok(format!("Error writing file: {}", e));
+    }
+    file_versions::record(Path::new(path));
+    Ok(format!(
+        "Lines written successfully. The edited region now reads:\n{}",
+        edits::numbered_snippet(&updated, first, last, 3)
+    ))
+}
+
+async fn execute_replace_in_file(
+    args: Value,
+    verbose: bool,
+    yolo: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let path = args["path"].as_str().unwrap_or("");
+    let old_string = args["old_string"].as_str().unwrap_or("");
+    let new_string = args["new_string"].as_str().unwrap_or("");
+    let replace_all = arg_bool(&args, "replace_all", false);
+    let (existing, format) = match transaction::read_current(Path::new(path)) {
+        Ok(raw) => TextFormat::normalize(&raw),
+        Err(e) => return Ok(format!("Error reading file: {}", e)),
+    };
+    let warning = match check_stale_write(path, yolo) {
+        Ok(warning) => warning,
+        Err(rejection) => return Ok(rejection),
+    };
+    let replacement = match edits::replace_in_text(
+        &existing,
+        &old_string.replace("\r\n", "\n"),
+        &new_string.replace("\r\n", "\n"),
+        replace_all,
+    ) {
+        Ok(replacement) => replacement,
+        Err(e) => return Ok(format!("Error: {}. No changes were made.", e)),
+    };
+    let snippet = edits::numbered_snippet(
+        &replacement.content,
+        replacement.first_line,
+        replacement.last_line,
+        3,
+    );
+    if transaction::is_open() {
+        let rendered = format.render(&replacement.content);
+        return Ok(format!(
+            "{} The edited region will read:\n{}",
+            stage_edit(path, Some(rendered), warning),
+            snippet
+        ));
+    }
+    if !yolo
+        && !prompt_approval(
+            &format!(
+                "{}{}Do you want to apply this change to file `{}`? (Y/n): ",
+                edits::unified_diff(path, &existing, &replacement.content),
+                warning,
+                path
+            ),
+            verbose,
+        )
+    {
+        return Ok("File edit request declined by the user.".to_string());
+    }
+    let rendered = format.render(&replacement.content);
+    if let Err(e) = edits::write_atomic(Path::new(path), &rendered) {
+        return Ok(format!("Error writing file: {}", e));
+    }
+    file_versions::record(Path::new(path));
+    Ok(format!(
+        "Replaced {} occurrence(s) in {}. The edited region now reads:\n{}",
+        replacement.count, path, snippet
+    ))
+}
+
+async fn execute_apply_patch(
+    args: Value,
+    verbose: bool,
+    yolo: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let patch_text = args["patch"].as_str().unwrap_or("");
+    let resolved =
+        patch::parse_patch(patch_text).and_then(|p| patch::resolve_patches(&p));
+    let (changes, notes) = match resolved {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            return Ok(format!("Error: {}. No files were changed.", e));
+        }
+    };
+    if changes.is_empty() {
+        return Ok("The patch does not change any files.".to_string());
+    }
+    let mut warnings = Vec::new();
+    for change in &changes {
+        match check_stale_write(&change.path.to_string_lossy(), yolo) {
+            Ok(warning) => warnings.push(warning),
+            Err(rejection) => return Ok(rejection),
+        }
+    }
+    if transaction::is_open() {
+        for (change, warning) in changes.iter().zip(warnings) {
+            let updated = change.updated.clone();
+            let warning = Some(warning).filter(|w| !w.is_empty());
+            if let Err(e) = transaction::stage(&change.path, updated, warning) {
+                return Ok(format!("Error staging patch: {}", e));
             }
         }
-        Err(e) => Ok(format!("Error reading file: {}", e)),
+        return Ok(format!(
+            "Staged the patch to {} file(s). Call commit_edit to apply all staged changes or abort_edit to discard them.",
+            changes.len()
+        ));
+    }
+    if !yolo
+        && !prompt_approval(
+            &format!(
+                "{}{}Do you want to apply this patch to {} file(s)? (Y/n): ",
+                edits::combined_diff(&changes),
+                warnings.concat(),
+                changes.len()
+            ),
+            verbose,
+        )
+    {
+        return Ok("Patch request declined by the user.".to_string());
+    }
+    if let Err(e) = edits::commit_file_changes(&changes) {
+        return Ok(format!("Error applying patch: {}", e));
+    }
+    for change in &changes {
+        file_versions::record(&change.path);
+    }
+    let mut result = format!(
+        "Patch applied: {}",
+        changes.iter().map(|c| c.summary()).collect::<Vec<_>>().join(", ")
+    );
+    for note in notes {
+        result.push_str(&format!("\nNote: {}", note));
+    }
+    Ok(result)
+}
+
+async fn execute_begin_edit(
+    _args: Value,
+    _verbose: bool,
+    _yolo: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if !transaction::begin() {
+        return Ok("An edit is already in progress. Call commit_edit or abort_edit first.".to_string());
+    }
+    Ok(
+        "Edit started. File changes will be staged until commit_edit."
+            .to_string(),
+    )
+}
+
+async fn execute_commit_edit(
+    _args: Value,
+    verbose: bool,
+    yolo: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let Some(staged) = transaction::take() else {
+        return Ok("No edit is in progress. Call begin_edit first.".to_string());
+    };
+    if staged.changes.is_empty() {
+        return Ok("Edit closed; no files were changed.".to_string());
+    }
+    let warnings = staged.warnings.concat();
+    if yolo && !warnings.is_empty() {
+        return Ok(format!(
+            "{}None of the staged changes were applied. Read the files again with read_file and redo the edit.",
+            warnings
+        ));
+    }
+    if !yolo
+        && !prompt_approval(
+            &format!(
+                "{}{}Do you want to apply these changes to {} file(s)? (Y/n): ",
+                edits::combined_diff(&staged.changes),
+                warnings,
+                staged.changes.len()
+            ),
+            verbose,
+        )
+    {
+        return Ok(
+            "Edit declined by the user; no files were changed.".to_string()
+        );
+    }
+    if let Err(e) = transaction::commit(&staged.changes) {
+        return Ok(format!("Error: {}. No files were changed.", e));
+    }
+    for change in &staged.changes {
+        file_versions::record(&change.path);
+    }
+    Ok(format!(
+        "Edit committed: {}",
+        staged
+            .changes
+            .iter()
+            .map(|c| c.summary())
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
+
+async fn execute_abort_edit(
+    _args: Value,
+    _verbose: bool,
+    _yolo: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    match transaction::take() {
+        Some(staged) => Ok(format!(
+            "Edit aborted; staged changes to {} file(s) were discarded.",
+            staged.changes.len()
+        )),
+        None => Ok("No edit is in progress.".to_string()),
     }
 }
 
@@ -321,7 +929,7 @@ async fn execute_finish_task(
     _yolo: bool,
 ) -> Result<String, Box<dyn std::error::Error>> {
     let message = args["message"].as_str().unwrap_or("");
-    println!("{}", format!("Task completed: {}", message));
+    println!("Task completed: {}", message);
     Ok(format!("Task completed: {}", message))
 }
 
@@ -331,7 +939,7 @@ async fn execute_finish_planning(
     _yolo: bool,
 ) -> Result<String, Box<dyn std::error::Error>> {
     let message = args["message"].as_str().unwrap_or("");
-    println!("{}", format!("Planning completed: {}", message));
+    println!("Planning completed: {}", message);
     Ok(format!("Planning completed: {}", message))
 }
 
@@ -341,7 +949,7 @@ async fn execute_ask_for_clarification(
     _yolo: bool,
 ) -> Result<String, Box<dyn std::error::Error>> {
     let question = args["question"].as_str().unwrap_or("");
-    println!("{}", format!("Clarifying question for user: {}", question));
+    println!("Clarifying question for user: {}", question);
     let mut answer = String::new();
     io::stdin().read_line(&mut answer).unwrap();
     Ok(answer.trim().to_string())
@@ -352,25 +960,224 @@ async fn execute_list_files(
     _verbose: bool,
     _yolo: bool,
 ) -> Result<String, Box<dyn std::error::Error>> {
-    let path = args["path"].as_str().unwrap_or("");
-    let output = match process::Command::new("gls")
-        .arg(path)
-        .arg("--ignore=target")
-        .output()
+    let path = arg_path(&args, "path");
+    let options = ListOptions {
+        depth: arg_usize(&args, "depth", 2),
+        tree: arg_bool(&args, "tree", false),
+        glob: arg_glob(&args, "glob"),
+        sizes: arg_bool(&args, "sizes", false),
+        max_entries: arg_usize(&args, "max_entries", 200),
+    };
+    match file_search::list_files(Path::new(path), &options) {
+        Ok(listing) => Ok(listing),
+        Err(e) => Ok(format!("Error listing files: {}", e)),
+    }
+}
+
+fn arg_glob(args: &Value, key: &str) -> Option<String> {
+    args[key].as_str().filter(|g| !g.trim().is_empty()).map(|g| g.to_string())
+}
+
+async fn execute_search_files(
+    args: Value,
+    _verbose: bool,
+    _yolo: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let pattern = args["pattern"].as_str().unwrap_or("");
+    let path = arg_path(&args, "path");
+    let options = SearchOptions {
+        literal: arg_bool(&args, "literal", false),
+        case_insensitive: arg_bool(&args, "case_insensitive", false),
+        include: arg_glob(&args, "include"),
+        exclude: arg_glob(&args, "exclude"),
+        context: arg_usize(&args, "context", 0),
+        max_results: arg_usize(&args, "max_results", 100),
+    };
+    match file_search::search_files(Path::new(path), pattern, &options) {
+        Ok(matches) => Ok(matches),
+        Err(e) => Ok(format!("Error searching files: {}", e)),
+    }
+}
+
+async fn execute_find_files(
+    args: Value,
+    _verbose: bool,
+    _yolo: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let pattern = args["pattern"].as_str().unwrap_or("");
+    let path = arg_path(&args, "path");
+    let sort = match args["sort"].as_str().map(|s| s.trim().to_lowercase()) {
+        Some(s) if s == "modified" || s == "mtime" => FindSort::Modified,
+        _ => FindSort::Name,
+    };
+    let max_results = arg_usize(&args, "max_results", 100);
+    match file_search::find_files(Path::new(path), pattern, sort, max_results) {
+        Ok(files) => Ok(files),
+        Err(e) => Ok(format!("Error finding files: {}", e)),
+    }
+}
+
+fn arg_headers(args: &Value) -> Result<Vec<(String, String)>, String> {
+    let lines: Vec<String> = match &args["headers"] {
+        Value::Array(items) => items
+            .iter()
+            .filter_map(|v| v.as_str().map(|s| s.to_string()))
+            .collect(),
+        Value::String(s) => s.lines().map(|l| l.to_string()).collect(),
+        _ => Vec::new(),
+    };
+    lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| match line.split_once(':') {
+            Some((name, value)) => {
+                Ok((name.trim().to_string(), value.trim().to_string()))
+            }
+            None => Err(format!(
+                "Invalid header `{}`, expected 'Name: value'",
+                line
+            )),
+        })
+        .collect()
+}
+
+async fn execute_http_request(
+    args: Value,
+    verbose: bool,
+    yolo: bool,
+    options: &HttpOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let url = args["url"].as_str().unwrap_or("").trim();
+    let method = args["method"].as_str().unwrap_or("GET").trim().to_uppercase();
+    let method = match reqwest::Method::from_bytes(method.as_bytes()) {
+        Ok(method) => method,
+        Err(_) => return Ok(format!("Invalid HTTP method `{}`", method)),
+    };
+    if options.read_only && !http::is_read_only(&method) {
+        return Ok(
+            "Only GET and HEAD requests are allowed in plan mode".to_string()
+        );
+    }
+    let headers = match arg_headers(&args) {
+        Ok(headers) => headers,
+        Err(e) => return Ok(e),
+    };
+    let body = args["body"].as_str().map(|b| b.to_string());
+    if !yolo
+        && !http::is_read_only(&method)
+        && !prompt_approval(
+            &format!(
+                "Do you want to send a {} request to `{}`{}? (Y/n): ",
+                method,
+                url,
+                body.as_deref()
+                    .map(|b| format!(" with body `{}`", truncate_content(b)))
+                    .unwrap_or_default()
+            ),
+            verbose,
+        )
     {
-        Ok(output) => output,
-        Err(e) => {
-            return Err(format!("Failed to execute list_files: {}", e).into());
-        }
+        return Ok("HTTP request declined by the user.".to_string());
+    }
+    let timeout = match arg_usize(&args, "timeout", 0) {
+        0 => None,
+        secs => Some(std::time::Duration::from_secs(secs as u64)),
     };
-    let mut result = format!("{}", String::from_utf8_lossy(&output.stdout));
-    if !output.stderr.is_empty() {
-        result.push_str(&format!(
-            "\nStderr: {}",
-            String::from_utf8_lossy(&output.stderr)
-        ));
+    let request = HttpRequest {
+        method: method.clone(),
+        url: url.to_string(),
+        headers,
+        body,
+        timeout,
+    };
+    match http::send(&request, options).await {
+        Ok(response) => Ok(http::format_response(
+            &response,
+            &method,
+            arg_bool(&args, "raw", false),
+        )),
+        Err(e) => Ok(format!("Error: {}", e)),
+    }
+}
+
+async fn execute_web_search(
+    args: Value,
+    _verbose: bool,
+    _yolo: bool,
+    options: &WebSearchOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let query = args["query"].as_str().unwrap_or("").trim();
+    if query.is_empty() {
+        return Ok("Error: query must not be empty".to_string());
+    }
+    let max_results = arg_usize(&args, "max_results", options.max_results);
+    match options.backend.search(query, max_results).await {
+        Ok(results) => Ok(web_search::format_results(query, &results)),
+        Err(e) => Ok(format!(
+            "Error searching with {}: {}",
+            options.backend.name(),
+            e
+        )),
+    }
+}
+
+async fn execute_command_tool(
+    args: Value,
+    verbose: bool,
+    yolo: bool,
+    tool: &CommandTool,
+    options: &ShellOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let command = match tool.render(&args) {
+        Ok(command) => command,
+        Err(e) => return Ok(format!("Error: {}", e)),
+    };
+    if !yolo
+        && tool.require_approval
+        && !prompt_approval(
+            &format!(
+                "Do you want to run this command: `{}` ? (Y/n): ",
+                command
+            ),
+            verbose,
+        )
+    {
+        return Ok(
+            "Command execution request declined by the user.".to_string()
+        );
+    }
+    let options = ShellOptions {
+        pty: false,
+        timeout: Some(Duration::from_secs(tool.timeout_secs)),
+        ..options.clone()
+    };
+    shell::run_command(&CommandSpec::Shell(command), &options)
+}
+
+async fn execute_mcp_tool(
+    args: Value,
+    verbose: bool,
+    yolo: bool,
+    tool: &McpTool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if !yolo
+        && !tool.read_only
+        && !prompt_approval(
+            &format!(
+                "Do you want to call `{}` on MCP server `{}` with arguments `{}`? (Y/n): ",
+                tool.remote_name,
+                tool.server.name,
+                truncate_content(&args.to_string())
+            ),
+            verbose,
+        )
+    {
+        return Ok("MCP tool call declined by the user.".to_string());
+    }
+    match tool.call(args).await {
+        Ok(output) => Ok(output),
+        Err(e) => Ok(format!("Error from {}: {}", tool.name, e)),
     }
-    Ok(result)
 }
 
 async fn execute_describe_to_user(
@@ -379,7 +1186,7 @@ async fn execute_describe_to_user(
     _yolo: bool,
 ) -> Result<String, Box<dyn std::error::Error>> {
     let description = args["description"].as_str().unwrap_or("");
-    println!("{}", format!("Description: {}", description));
+    println!("Description: {}", description);
     Ok("Description successfully presented to user".to_string())
 }
 
@@ -388,6 +1195,7 @@ pub fn get_tools(
     plan_mode: bool,
     no_shell: bool,
     no_clarify: bool,
+    tool_config: &ToolConfig,
 ) -> Vec<Tool> {
     let finish_tool = if plan_mode {
         Tool::FinishPlanning
@@ -395,24 +1203,116 @@ pub fn get_tools(
         Tool::FinishTask
     };
     let mut tools = vec![
-        Tool::ExecuteShellCommand,
+        Tool::ExecuteShellCommand(tool_config.shell.clone()),
         Tool::ReadFile,
         Tool::WriteFile,
         Tool::ReadLines,
         Tool::WriteLines,
-        // Tool::ListFiles,
+        Tool::ReplaceInFile,
+        Tool::ApplyPatch,
+        Tool::BeginEdit,
+        Tool::CommitEdit,
+        Tool::AbortEdit,
+        Tool::ListFiles,
+        Tool::SearchFiles,
+        Tool::FindFiles,
+        Tool::HttpRequest(HttpOptions {
+            read_only: plan_mode,
+            ..tool_config.http.clone()
+        }),
         finish_tool,
         Tool::DescribeToUser,
         Tool::AskForClarification,
     ];
+    if let Some(options) = &tool_config.web_search {
+        let position = tools
+            .iter()
+            .position(|t| matches!(t, Tool::HttpRequest(_)))
+            .map_or(tools.len(), |i| i + 1);
+        tools.insert(position, Tool::WebSearch(options.clone()));
+    }
+    if tool_config.shell.pty {
+        tools.insert(1, Tool::WriteToPty);
+    }
+    // Built-in tools keep their names if a configured tool reuses one, and
+    // command tools win over MCP tools.
+    for tool in &tool_config.command_tools {
+        if (tool.read_only || !plan_mode)
+            && !tools.iter().any(|t| t.name() == tool.name)
+        {
+            tools.push(Tool::Command(tool.clone(), tool_config.shell.clone()));
+        }
+    }
+    for tool in &tool_config.mcp_tools {
+        if (tool.read_only || !plan_mode)
+            && !tools.iter().any(|t| t.name() == tool.name)
+        {
+            tools.push(Tool::Mcp(tool.clone()));
+        }
+    }
     if plan_mode {
-        tools.retain(|t| !matches!(t, Tool::WriteFile | Tool::WriteLines));
+        tools.retain(|t| {
+            !matches!(
+                t,
+                Tool::WriteFile
+                    | Tool::WriteLines
+                    | Tool::ReplaceInFile
+                    | Tool::ApplyPatch
+                    | Tool::BeginEdit
+                    | Tool::CommitEdit
+                    | Tool::AbortEdit
+            )
+        });
     }
     if no_shell {
-        tools.retain(|t| !matches!(t, Tool::ExecuteShellCommand));
+        tools.retain(|t| {
+            !matches!(t, Tool::ExecuteShellCommand(_) | Tool::WriteToPty)
+        });
     }
     if yolo || no_clarify {
         tools.retain(|t| !matches!(t, Tool::AskForClarification));
     }
     tools
+        .retain(|t| !tool_config.policy.disabled.iter().any(|d| d == t.name()));
+    tools
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_truncate_content_on_char_boundary() {
+        let content = "é".repeat(300);
+        assert_eq!(
+            truncate_content(&content),
+            format!("{}[... and 50 more characters]", "é".repeat(250))
+        );
+        assert_eq!(truncate_content("short"), "short");
+    }
+
+    #[tokio::test]
+    async fn test_patch_and_replace_refuse_unread_files_in_yolo_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("unread.txt");
+        fs::write(&path, "old\n").unwrap();
+        let patch = format!(
+            "*** Begin Patch\n*** Update File: {}\n@@\n-old\n+new\n*** End Patch",
+            path.display()
+        );
+        let result =
+            execute_apply_patch(json!({ "patch": patch }), false, true)
+                .await
+                .unwrap();
+        assert!(result.contains("has not been read"));
+        let args = json!({
+            "path": path.to_str().unwrap(),
+            "old_string": "old",
+            "new_string": "new",
+        });
+        let result = execute_replace_in_file(args, false, true).await.unwrap();
+        assert!(result.contains("has not been read"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old\n");
+    }
 }
@@ -0,0 +1,182 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::edits::{self, FileChange};
+
+// Edits staged between begin_edit and commit_edit. While a transaction is
+// open, file tools stage their changes here instead of writing them.
+pub struct Transaction {
+    pub changes: Vec<FileChange>,
+    // Stale-write warnings raised while staging, shown again at commit time.
+    pub warnings: Vec<String>,
+}
+
+static OPEN: Mutex<Option<Transaction>> = Mutex::new(None);
+
+// `path` made absolute, with symlinks of existing directories resolved, so
+// that `./a`, `a` and `/cwd/a` name the same staged file.
+fn normalize(path: &Path) -> PathBuf {
+    if let Ok(path) = fs::canonicalize(path) {
+        return path;
+    }
+    let absolute: PathBuf = env::current_dir()
+        .map(|dir| dir.join(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+        .components()
+        .collect();
+    match (absolute.parent(), absolute.file_name()) {
+        (Some(parent), Some(name)) => fs::canonicalize(parent)
+            .map(|parent| parent.join(name))
+            .unwrap_or(absolute),
+        _ => absolute,
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    a == b || normalize(a) == normalize(b)
+}
+
+pub fn begin() -> bool {
+    let mut open = OPEN.lock().unwrap();
+    if open.is_some() {
+        return false;
+    }
+    *open = Some(Transaction {
+        changes: Vec::new(),
+        warnings: Vec::new(),
+    });
+    true
+}
+
+pub fn is_open() -> bool {
+    OPEN.lock().unwrap().is_some()
+}
+
+pub fn is_staged(path: &Path) -> bool {
+    OPEN.lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|t| t.changes.iter().any(|c| same_file(&c.path, path)))
+}
+
+// Reads `path` as it would be after the staged changes, falling back to the
+// file on disk.
+pub fn read_current(path: &Path) -> io::Result<String> {
+    let open = OPEN.lock().unwrap();
+    let staged = open
+        .as_ref()
+        .and_then(|t| t.changes.iter().find(|c| same_file(&c.path, path)));
+    match staged {
+        Some(FileChange {
+            updated: Some(content),
+            ..
+        }) => Ok(content.clone()),
+        Some(FileChange { updated: None, .. }) => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "the file is deleted by a staged change",
+        )),
+        None => fs::read_to_string(path),
+    }
+}
+
+// Stages `updated` (None deletes) as the new contents of `path`. Returns the
+// number of files with staged changes.
+pub fn stage(
+    path: &Path,
+    updated: Option<String>,
+    warning: Option<String>,
+) -> Result<usize, String> {
+    let mut open = OPEN.lock().unwrap();
+    let transaction = open.as_mut().ok_or("no edit is in progress")?;
+    match transaction.changes.iter_mut().find(|c| same_file(&c.path, path)) {
+        Some(change) => change.updated = updated,
+        None => {
+            let original = match fs::read_to_string(path) {
+                Ok(content) => Some(content),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    return Err(format!(
+                        "cannot read {}: {}",
+                        path.display(),
+                        e
+                    ));
+                }
+            };
+            transaction.changes.push(FileChange {
+                path: path.to_path_buf(),
+                original,
+                updated,
+            });
+        }
+    }
+    transaction.warnings.extend(warning);
+    Ok(transaction.changes.len())
+}
+
+// Closes the open transaction, returning its effective changes.
+pub fn take() -> Option<Transaction> {
+    let mut transaction = OPEN.lock().unwrap().take()?;
+    transaction.changes.retain(|c| c.original != c.updated);
+    Some(transaction)
+}
+
+// Applies a closed transaction all-or-nothing, refusing if any file was
+// changed on disk after its changes were staged.
+pub fn commit(changes: &[FileChange]) -> Result<(), String> {
+    for change in changes {
+        let on_disk = fs::read_to_string(&change.path).ok();
+        if on_disk != change.original {
+            return Err(format!(
+                "{} changed on disk after the edit was staged",
+                change.path.display()
+            ));
+        }
+    }
+    edits::commit_file_changes(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transaction_stages_and_commits_together() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.txt");
+        let b = dir.path().join("b.txt");
+        fs::write(&a, "a\n").unwrap();
+
+        assert!(begin());
+        assert!(!begin());
+        stage(&a, Some("a2\n".to_string()), None).unwrap();
+        assert_eq!(stage(&b, Some("b\n".to_string()), None).unwrap(), 2);
+        assert_eq!(read_current(&a).unwrap(), "a2\n");
+        // Another spelling of the same path is the same staged file.
+        let a_dotted = dir.path().join(".").join("a.txt");
+        assert!(is_staged(&a_dotted));
+        assert_eq!(
+            stage(&a_dotted, Some("a2\n".to_string()), None).unwrap(),
+            2
+        );
+        assert!(is_staged(&dir.path().join("./b.txt")));
+        assert_eq!(fs::read_to_string(&a).unwrap(), "a\n");
+        assert!(!b.exists());
+
+        let transaction = take().unwrap();
+        assert!(!is_open());
+        commit(&transaction.changes).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "a2\n");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "b\n");
+
+        assert!(begin());
+        stage(&a, Some("a3\n".to_string()), None).unwrap();
+        fs::write(&a, "edited elsewhere\n").unwrap();
+        let transaction = take().unwrap();
+        let err = commit(&transaction.changes).unwrap_err();
+        assert!(err.contains("changed on disk"));
+        assert_eq!(fs::read_to_string(&a).unwrap(), "edited elsewhere\n");
+    }
+}
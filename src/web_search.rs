@@ -0,0 +1,161 @@
+use async_trait::async_trait;
+use reqwest::Url;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, PartialEq)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    pub snippet: String,
+}
+
+#[async_trait]
+pub trait SearchBackend: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn search(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Result<Vec<SearchResult>, String>;
+}
+
+#[derive(Clone)]
+pub struct WebSearchOptions {
+    pub backend: Arc<dyn SearchBackend>,
+    pub max_results: usize,
+}
+
+// Talks to the JSON API of a SearxNG instance (`/search?format=json`), for
+// example one running locally in a container.
+pub struct SearxngBackend {
+    pub base_url: String,
+    pub timeout: Duration,
+}
+
+#[derive(Deserialize)]
+struct SearxngResponse {
+    #[serde(default)]
+    results: Vec<SearxngResult>,
+}
+
+#[derive(Deserialize)]
+struct SearxngResult {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    url: String,
+    #[serde(default)]
+    content: String,
+}
+
+#[async_trait]
+impl SearchBackend for SearxngBackend {
+    fn name(&self) -> &str {
+        "searxng"
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Result<Vec<SearchResult>, String> {
+        let endpoint =
+            format!("{}/search", self.base_url.trim_end_matches('/'));
+        let url = Url::parse_with_params(
+            &endpoint,
+            &[("q", query), ("format", "json")],
+        )
+        .map_err(|e| format!("invalid search URL {}: {}", endpoint, e))?;
+        let client = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let response =
+            client.get(url).send().await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        let body = response.text().await.map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            return Err(format!("search backend returned {}", status));
+        }
+        let parsed: SearxngResponse = serde_json::from_str(&body)
+            .map_err(|e| format!("unexpected search response: {}", e))?;
+        Ok(parsed
+            .results
+            .into_iter()
+            .take(max_results)
+            .map(|r| SearchResult {
+                title: r.title.trim().to_string(),
+                url: r.url,
+                snippet: r
+                    .content
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            })
+            .collect())
+    }
+}
+
+pub fn format_results(query: &str, results: &[SearchResult]) -> String {
+    if results.is_empty() {
+        return format!("No results for `{}`", query);
+    }
+    results
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let mut entry = format!("{}. {}\n   {}", i + 1, r.title, r.url);
+            if !r.snippet.is_empty() {
+                entry.push_str(&format!("\n   {}", r.snippet));
+            }
+            entry
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::test_server::{response, serve_once};
+
+    #[tokio::test]
+    async fn test_searxng_backend_parses_results() {
+        let body = r#"{"query": "rust", "results": [
+            {"title": "Rust ", "url": "https://www.rust-lang.org/", "content": "A language\n empowering everyone"},
+            {"title": "Rust docs", "url": "https://doc.rust-lang.org/"},
+            {"title": "Third", "url": "https://example.com/"}
+        ]}"#;
+        let (url, server) =
+            serve_once(response("200 OK", "application/json", body)).await;
+        let backend = SearxngBackend {
+            base_url: format!("{}/", url),
+            timeout: Duration::from_secs(5),
+        };
+        let results = backend.search("rust lang", 2).await.unwrap();
+        let request = server.await.unwrap();
+        assert!(request.starts_with("GET /search?q=rust+lang&format=json "));
+        assert_eq!(
+            results,
+            vec![
+                SearchResult {
+                    title: "Rust".to_string(),
+                    url: "https://www.rust-lang.org/".to_string(),
+                    snippet: "A language empowering everyone".to_string(),
+                },
+                SearchResult {
+                    title: "Rust docs".to_string(),
+                    url: "https://doc.rust-lang.org/".to_string(),
+                    snippet: String::new(),
+                },
+            ]
+        );
+        assert_eq!(
+            format_results("rust lang", &results),
+            "1. Rust\n   https://www.rust-lang.org/\n   A language empowering everyone\n2. Rust docs\n   https://doc.rust-lang.org/"
+        );
+    }
+}
@@ -1,210 +1,451 @@
+use crate::command_tools::CommandTool;
+use crate::http::HttpOptions;
+use crate::mcp;
+use crate::providers::{self, Provider};
 use crate::response_formats::ToolResponseFormat;
-use serde::Deserialize;
+use crate::settings::Settings;
+use crate::shell::ShellOptions;
+use crate::tools::ToolConfig;
+use crate::trust;
+use crate::web_search::{SearxngBackend, WebSearchOptions};
+use minijinja::{Environment, Value as TemplateValue};
 use serde_yaml::{Mapping, Value as YamlValue};
 use std::env;
 use std::fs;
 use std::path::Path;
-
-#[derive(Deserialize)]
-pub struct Config {
-    model: Option<String>,
-    format: Option<String>,
-}
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
 
 pub static DEFAULT_SYSTEM_PROMPT_YAML: &str =
     include_str!("../system_prompt.yaml");
+pub const PROJECT_SYSTEM_PROMPT: &str = ".attotool/system_prompt.yaml";
 
-pub fn get_default_model() -> String {
-    let config_path = format!(
-        "{}/.config/attotool/config.yaml",
-        env::var("HOME").expect("HOME not set")
-    );
-    let mut model = "mistralai/mistral-small-3.1-24b-instruct".to_string();
-    if Path::new(&config_path).exists() {
-        if let Ok(content) = fs::read_to_string(&config_path) {
-            if let Ok(config) = serde_yaml::from_str::<Config>(&content) {
-                if let Some(m) = config.model {
-                    model = m;
-                }
+pub fn get_provider(settings: &Settings) -> Result<Provider, String> {
+    providers::resolve(
+        &settings.provider,
+        &settings.providers,
+        settings.base_url.clone(),
+    )
+}
+
+pub fn get_response_format(settings: &Settings) -> ToolResponseFormat {
+    match ToolResponseFormat::from_str(&settings.format) {
+        Ok(format) => format,
+        Err(e) => {
+            eprintln!("Warning: {}", e);
+            ToolResponseFormat::Yaml
+        }
+    }
+}
+
+pub async fn get_tool_config(
+    settings: &Settings,
+    stream: bool,
+    with_mcp: bool,
+) -> ToolConfig {
+    ToolConfig {
+        shell: get_shell_options(settings, stream),
+        http: get_http_options(settings),
+        web_search: get_web_search_options(settings),
+        command_tools: get_command_tools(settings),
+        mcp_tools: if with_mcp {
+            mcp::load_tools(&settings.mcp_servers).await
+        } else {
+            Vec::new()
+        },
+        policy: settings.tools.clone(),
+    }
+}
+
+fn get_command_tools(settings: &Settings) -> Vec<CommandTool> {
+    settings
+        .command_tools
+        .iter()
+        .filter(|tool| match tool.validate() {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("Warning: command tool `{}`: {}", tool.name, e);
+                false
             }
+        })
+        .cloned()
+        .collect()
+}
+
+fn get_web_search_options(settings: &Settings) -> Option<WebSearchOptions> {
+    let search = settings.web_search.as_ref()?;
+    let backend = match search.backend.as_deref().unwrap_or("searxng") {
+        "searxng" => Arc::new(SearxngBackend {
+            base_url: search.url.clone(),
+            timeout: Duration::from_secs(search.timeout_secs.unwrap_or(15)),
+        }),
+        other => {
+            eprintln!("Warning: unknown web_search backend `{}`", other);
+            return None;
         }
+    };
+    Some(WebSearchOptions {
+        backend,
+        max_results: search.max_results.unwrap_or(8),
+    })
+}
+
+fn get_http_options(settings: &Settings) -> HttpOptions {
+    let mut options = HttpOptions::default();
+    let Some(http) = settings.http.as_ref() else {
+        return options;
+    };
+    if let Some(domains) = &http.allow_domains {
+        options.allow_domains = domains.clone();
+    }
+    if let Some(domains) = &http.deny_domains {
+        options.deny_domains = domains.clone();
+    }
+    if let Some(max) = http.max_response_bytes {
+        options.max_response_bytes = max;
+    }
+    if let Some(secs) = http.timeout_secs {
+        options.timeout = Duration::from_secs(secs);
     }
-    model
+    options
 }
 
-pub fn get_default_format() -> crate::response_formats::ToolResponseFormat {
-    let config_path = format!(
-        "{}/.config/attotool/config.yaml",
-        env::var("HOME").expect("HOME not set")
-    );
-    let mut format = crate::response_formats::ToolResponseFormat::Yaml;
-    if Path::new(&config_path).exists() {
-        if let Ok(content) = fs::read_to_string(&config_path) {
-            if let Ok(config) = serde_yaml::from_str::<Config>(&content) {
-                if let Some(f) = config.format {
-                    match crate::response_formats::ToolResponseFormat::from_str(
-                        &f,
-                    ) {
-                        Ok(parsed) => format = parsed,
-                        Err(e) => eprintln!("Warning: {}", e),
+fn get_shell_options(settings: &Settings, stream: bool) -> ShellOptions {
+    let mut options = ShellOptions {
+        pty: settings.pty,
+        stream,
+        shell: settings.shell.clone(),
+        env: settings.env.clone(),
+        ..Default::default()
+    };
+    let dotenv = env::current_dir()
+        .map(|dir| trust::project_file(&dir, ".env"))
+        .unwrap_or_else(|_| ".env".into());
+    if settings.dotenv && settings.trusted && dotenv.exists() {
+        match dotenvy::from_path_iter(&dotenv) {
+            Ok(iter) => {
+                for item in iter {
+                    match item {
+                        Ok((key, value)) => {
+                            options.env.insert(key, value);
+                        }
+                        Err(e) => eprintln!("Warning: .env: {}", e),
                     }
                 }
             }
+            Err(e) => eprintln!("Warning: .env: {}", e),
         }
     }
-    format
+    options
 }
 
-pub fn get_format(
-    fmt_str: Option<&str>,
-    default: crate::response_formats::ToolResponseFormat,
-) -> crate::response_formats::ToolResponseFormat {
-    if let Some(s) = fmt_str {
-        match crate::response_formats::ToolResponseFormat::from_str(s) {
-            Ok(f) => f,
-            Err(e) => {
-                eprintln!("Warning: {}", e);
-                default
-            }
+// An override of the form `{append: ..., prepend: ...}`, which extends the
+// base value instead of replacing it.
+fn is_directive(value: &YamlValue) -> bool {
+    value.as_mapping().is_some_and(|map| {
+        !map.is_empty()
+            && map
+                .keys()
+                .all(|k| matches!(k.as_str(), Some("append" | "prepend")))
+    })
+}
+
+fn apply_directive(
+    base: Option<&YamlValue>,
+    directive: &YamlValue,
+) -> YamlValue {
+    let prepend = directive.get("prepend");
+    let append = directive.get("append");
+    match base {
+        Some(YamlValue::Sequence(items)) => {
+            let as_items = |v: Option<&YamlValue>| match v {
+                Some(YamlValue::Sequence(more)) => more.clone(),
+                Some(other) => vec![other.clone()],
+                None => Vec::new(),
+            };
+            let mut result = as_items(prepend);
+            result.extend(items.iter().cloned());
+            result.extend(as_items(append));
+            YamlValue::Sequence(result)
+        }
+        base => {
+            let text = |v: Option<&YamlValue>| match v {
+                Some(YamlValue::String(s)) => s.clone(),
+                Some(other) => serde_yaml::to_string(other)
+                    .unwrap_or_default()
+                    .trim_end()
+                    .to_string(),
+                None => String::new(),
+            };
+            let parts = [text(prepend), text(base), text(append)];
+            YamlValue::String(
+                parts
+                    .iter()
+                    .filter(|p| !p.is_empty())
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )
         }
-    } else {
-        default
     }
 }
 
+// Deep-merges `user` over `base`: mappings are merged key by key, an
+// `append`/`prepend` directive extends the base string or list, and any
+// other value replaces the base one.
 pub fn merge_yaml(
     base: &serde_yaml::Value,
     user: &serde_yaml::Value,
 ) -> serde_yaml::Value {
-    let mut result = base.clone();
-    if let serde_yaml::Value::Mapping(ref mut res_map) = result {
-        if let serde_yaml::Value::Mapping(user_map) = user {
-            for (key, user_val) in user_map {
-                res_map.insert(key.clone(), user_val.clone());
+    let (YamlValue::Mapping(base_map), YamlValue::Mapping(user_map)) =
+        (base, user)
+    else {
+        return base.clone();
+    };
+    let mut result = base_map.clone();
+    for (key, user_val) in user_map {
+        let merged = match (base_map.get(key), user_val) {
+            (base_val, _) if is_directive(user_val) => {
+                apply_directive(base_val, user_val)
+            }
+            (Some(base_val @ YamlValue::Mapping(_)), YamlValue::Mapping(_)) => {
+                merge_yaml(base_val, user_val)
             }
+            _ => user_val.clone(),
+        };
+        result.insert(key.clone(), merged);
+    }
+    YamlValue::Mapping(result)
+}
+
+// Used when a system prompt file has no `layout`. `identity`, `guidance`
+// and `examples` are picked by format and mode; `agents_md` and `plan_mode`
+// only appear when they apply.
+const DEFAULT_LAYOUT: [&str; 8] = [
+    "identity",
+    "task_instructions",
+    "guidance",
+    "current_dir",
+    "agents_md",
+    "plan_mode",
+    "tools",
+    "examples",
+];
+
+fn today() -> String {
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        libc::localtime_r(&now, &mut tm);
+    }
+    format!(
+        "{:04}-{:02}-{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday
+    )
+}
+
+fn git_output(current_dir: &Path, args: &[&str]) -> String {
+    Command::new("git")
+        .args(args)
+        .current_dir(current_dir)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        })
+        .unwrap_or_default()
+}
+
+fn render_section(
+    env: &Environment,
+    name: &str,
+    text: &str,
+    context: &TemplateValue,
+) -> String {
+    match env.render_str(text, context) {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            eprintln!("Warning: system prompt section `{}`: {}", name, e);
+            text.to_string()
         }
     }
-    result
 }
 
-pub fn format_system_prompt_from_yaml(
+// The run options that change which sections the system prompt has.
+#[derive(Clone, Copy, Default)]
+pub struct PromptFlags {
+    pub disable_agents_md: bool,
+    pub plan_mode: bool,
+    pub yolo: bool,
+    pub no_clarify: bool,
+    // Whether the project's .attotool/system_prompt.yaml is layered over
+    // the user's.
+    pub project_prompt: bool,
+}
+
+// The rendered, non-empty layout entries, each with its name.
+pub fn system_prompt_sections_from_yaml(
     yaml: &serde_yaml::Value,
     current_dir: &Path,
-    disable_agents_md: bool,
-    plan_mode: bool,
+    flags: PromptFlags,
     available_tools_text: &str,
-    yolo: bool,
-    no_clarify: bool,
     response_format: &ToolResponseFormat,
-) -> String {
-    let current_dir_part = yaml["current_dir"]
-        .as_str()
-        .unwrap_or("{}")
-        .replace("{}", &current_dir.display().to_string());
-    let agents_md_field = yaml["agents_md"].as_str().unwrap_or("");
-    let agents_md_part = if !disable_agents_md && !agents_md_field.is_empty() {
-        format!("\n\n{}", agents_md_field)
-    } else {
-        String::new()
-    };
-    let plan_field = yaml["plan_mode"].as_str().unwrap_or("");
-    let plan_part = if plan_mode && !plan_field.is_empty() {
-        format!("\n\n{}", plan_field)
-    } else {
-        String::new()
-    };
-    let instructions = yaml["task_instructions"].as_str().unwrap_or("");
-    let base_guidance = if plan_mode {
-        yaml["plan_guidance"].as_str().unwrap_or("")
-    } else {
-        yaml["task_guidance"].as_str().unwrap_or("")
+    model: &str,
+    shell: &str,
+) -> Vec<(String, String)> {
+    let PromptFlags {
+        disable_agents_md,
+        plan_mode,
+        yolo,
+        no_clarify,
+        ..
+    } = flags;
+    let layout: Vec<&str> = match yaml["layout"].as_sequence() {
+        Some(names) => names.iter().filter_map(|n| n.as_str()).collect(),
+        None => DEFAULT_LAYOUT.to_vec(),
     };
-    let ask_for_clarification_field =
-        yaml["ask_for_clarification"].as_str().unwrap_or("");
-    let ask_for_clarification_part =
-        if !yolo && !no_clarify && !ask_for_clarification_field.is_empty() {
-            format!(" {}", ask_for_clarification_field)
-        } else {
-            String::new()
-        };
-
-    let guidance = format!("{}{}", base_guidance, ask_for_clarification_part);
-    let task_identity_part = format!("{}\n\n{}", instructions, guidance);
-    let identity = match response_format {
+    let (identity_key, examples_key) = match response_format {
         ToolResponseFormat::Yaml => {
-            yaml["yaml_tool_calling_agent_identity"].as_str().unwrap_or("")
+            ("yaml_tool_calling_agent_identity", "yaml_examples")
         }
         ToolResponseFormat::JsonVariableKeys => {
-            yaml["json_tool_calling_agent_identity"].as_str().unwrap_or("")
+            ("json_tool_calling_agent_identity", "json_examples")
         }
-        ToolResponseFormat::JsonFixedKeys => {
-            yaml["json_fixed_key_tool_calling_agent_identity"]
-                .as_str()
-                .unwrap_or("")
+        ToolResponseFormat::JsonFixedKeys => (
+            "json_fixed_key_tool_calling_agent_identity",
+            "json_fixed_key_examples",
+        ),
+    };
+    let guidance_key = if plan_mode {
+        "plan_guidance"
+    } else {
+        "task_guidance"
+    };
+    let clarify = !yolo && !no_clarify;
+    // The sections each layout entry is made of.
+    let parts: Vec<(&str, Vec<&str>)> = layout
+        .iter()
+        .map(|name| {
+            let keys = match *name {
+                "identity" => vec![identity_key],
+                "examples" => vec![examples_key],
+                "guidance" if clarify => {
+                    vec![guidance_key, "ask_for_clarification"]
+                }
+                "guidance" => vec![guidance_key],
+                "agents_md" if disable_agents_md => vec![],
+                "plan_mode" if !plan_mode => vec![],
+                key => vec![key],
+            };
+            (*name, keys)
+        })
+        .collect();
+    // Sections written before templating used `{}` for the value.
+    let text = |key: &str| -> String {
+        let text = yaml[key].as_str().unwrap_or("");
+        match key {
+            "current_dir" => text.replace("{}", "{{ cwd }}"),
+            "tools" => text.replace("{}", "{{ tools }}"),
+            _ => text.to_string(),
         }
     };
-    let examples_key = match response_format {
-        ToolResponseFormat::Yaml => "yaml_examples",
-        ToolResponseFormat::JsonVariableKeys => "json_examples",
-        ToolResponseFormat::JsonFixedKeys => "json_fixed_key_examples",
+
+    let templates = Environment::new();
+    // The git variables cost two commands per request, so they are only
+    // looked up when a section uses them.
+    let uses_git = parts.iter().flat_map(|(_, keys)| keys).any(|key| {
+        templates.template_from_str(&text(key)).is_ok_and(|t| {
+            let names = t.undeclared_variables(false);
+            names.contains("git_branch") || names.contains("git_status")
+        })
+    });
+    let (git_branch, git_status) = if uses_git {
+        (
+            git_output(current_dir, &["rev-parse", "--abbrev-ref", "HEAD"]),
+            git_output(current_dir, &["status", "--short"]),
+        )
+    } else {
+        (String::new(), String::new())
+    };
+    let context = minijinja::context! {
+        cwd => current_dir.display().to_string(),
+        date => today(),
+        os => env::consts::OS,
+        shell => shell,
+        git_branch => git_branch,
+        git_status => git_status,
+        model => model,
+        format => response_format.as_str(),
+        mode => if plan_mode { "plan" } else { "task" },
+        plan => plan_mode,
+        yolo => yolo,
+        clarify => clarify,
+        tools => available_tools_text,
     };
-    let examples =
-        yaml[examples_key].as_str().unwrap_or("").trim_start_matches('\n');
-    let system_content = format!(
-        "{}\n\n{}\n\n{}{}{}\n\n{}\n\n{}",
-        identity,
-        task_identity_part,
-        current_dir_part,
-        agents_md_part,
-        plan_part,
-        yaml["tools"]
-            .as_str()
-            .unwrap_or("{}")
-            .replace("{}", available_tools_text),
-        examples
-    );
-    system_content
+
+    parts
+        .iter()
+        .map(|(name, keys)| {
+            let part = keys
+                .iter()
+                .map(|key| {
+                    render_section(&templates, key, &text(key), &context)
+                })
+                .filter(|rendered| !rendered.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ");
+            (name.to_string(), part.trim_start_matches('\n').to_string())
+        })
+        .filter(|(_, part)| !part.is_empty())
+        .collect()
 }
 
-pub fn format_system_prompt(
+pub fn join_sections(sections: &[(String, String)]) -> String {
+    sections
+        .iter()
+        .map(|(_, text)| text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+// The system prompt as sent, split into its layout entries.
+pub fn system_prompt_sections(
     current_dir: &Path,
-    disable_agents_md: bool,
-    plan_mode: bool,
+    flags: PromptFlags,
     available_tools_text: &str,
-    yolo: bool,
-    no_clarify: bool,
     response_format: &ToolResponseFormat,
-) -> String {
+    model: &str,
+    shell: &str,
+) -> Vec<(String, String)> {
     let base_yaml: serde_yaml::Value =
         serde_yaml::from_str(DEFAULT_SYSTEM_PROMPT_YAML)
             .expect("Failed to parse default system prompt YAML");
     let home = env::var("HOME").unwrap_or("".to_string());
-    let user_config_path =
-        format!("{}/.config/attotool/system_prompt.yaml", home);
-    let user_yaml = if Path::new(&user_config_path).exists() {
-        Some(
-            fs::read_to_string(&user_config_path)
-                .expect("Failed to read user config"),
-        )
-    } else {
-        None
-    };
-    let merged_yaml = if let Some(ref user_str) = user_yaml {
-        let user_val: serde_yaml::Value = serde_yaml::from_str(user_str)
+    let mut paths =
+        vec![Path::new(&home).join(".config/attotool/system_prompt.yaml")];
+    if flags.project_prompt {
+        paths.push(trust::project_file(current_dir, PROJECT_SYSTEM_PROMPT));
+    }
+    let mut merged_yaml = base_yaml;
+    for path in paths.iter().filter(|p| p.exists()) {
+        let content =
+            fs::read_to_string(path).expect("Failed to read user config");
+        let layer: serde_yaml::Value = serde_yaml::from_str(&content)
             .expect("Failed to parse user system prompt YAML");
-        merge_yaml(&base_yaml, &user_val)
-    } else {
-        base_yaml
-    };
-    format_system_prompt_from_yaml(
+        merged_yaml = merge_yaml(&merged_yaml, &layer);
+    }
+    system_prompt_sections_from_yaml(
         &merged_yaml,
         current_dir,
-        disable_agents_md,
-        plan_mode,
+        flags,
         available_tools_text,
-        yolo,
-        no_clarify,
         response_format,
+        model,
+        shell,
     )
 }
 
@@ -258,6 +499,77 @@ mod tests {
     use super::*;
     use serde_yaml::{Mapping, Value as YamlValue};
 
+    #[test]
+    fn test_system_prompt_templates_and_layout() {
+        let defaults: YamlValue =
+            serde_yaml::from_str(DEFAULT_SYSTEM_PROMPT_YAML).unwrap();
+        let sections = system_prompt_sections_from_yaml(
+            &defaults,
+            Path::new("/work"),
+            PromptFlags::default(),
+            "TOOLS",
+            &ToolResponseFormat::Yaml,
+            "some-model",
+            "bash",
+        );
+        let names: Vec<&str> =
+            sections.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "identity",
+                "task_instructions",
+                "guidance",
+                "current_dir",
+                "agents_md",
+                "tools",
+                "examples"
+            ]
+        );
+        let prompt = join_sections(&sections);
+        assert!(prompt.starts_with("You are a tool calling agent"));
+        assert!(prompt.contains(
+            "If the task is finished, use the finish_task tool. If you need additional information, use ask_for_clarification\n\nThe current working directory is /work\n\nAGENTS.md is an open format"
+        ));
+        assert!(prompt.contains(
+            "codebase.\n\nYour available tools:\n\nTOOLS\n\nAn example of appropriate response formatting:\n\nread_file:"
+        ));
+        assert!(!prompt.contains("PLAN MODE"));
+
+        let user: YamlValue = serde_yaml::from_str(
+            r#"
+layout: [current_dir, team, tools]
+current_dir: "Working in {}"
+team: "{% if mode == 'plan' %}Plan for {{ model }} ({{ format }}).{% endif %}"
+tools: "Tools: {{ tools }} {% endif %}"
+"#,
+        )
+        .unwrap();
+        let merged = merge_yaml(&defaults, &user);
+        let render = |plan_mode| {
+            join_sections(&system_prompt_sections_from_yaml(
+                &merged,
+                Path::new("/work"),
+                PromptFlags {
+                    plan_mode,
+                    ..Default::default()
+                },
+                "TOOLS",
+                &ToolResponseFormat::JsonFixedKeys,
+                "some-model",
+                "bash",
+            ))
+        };
+        assert_eq!(
+            render(true),
+            "Working in /work\n\nPlan for some-model (json_fixed_key).\n\nTools: {{ tools }} {% endif %}"
+        );
+        assert_eq!(
+            render(false),
+            "Working in /work\n\nTools: {{ tools }} {% endif %}"
+        );
+    }
+
     #[test]
     fn test_merge_yaml_simple_override() {
         let base_yaml =
@@ -312,11 +624,9 @@ mod tests {
                     config[&YamlValue::String("timeout".to_string())],
                     YamlValue::Number(60.into())
                 );
-                // Note: merge_yaml does shallow merge, so retries is replaced
-                assert!(
-                    !config.contains_key(&YamlValue::String(
-                        "retries".to_string()
-                    ))
+                assert_eq!(
+                    config[&YamlValue::String("retries".to_string())],
+                    YamlValue::Number(3.into())
                 );
             } else {
                 panic!("Expected nested mapping");
@@ -325,4 +635,34 @@ mod tests {
             panic!("Expected mapping");
         }
     }
+
+    #[test]
+    fn test_merge_yaml_append_and_prepend() {
+        let base_yaml = serde_yaml::from_str(
+            "task_guidance: Finish with finish_task.\nlayout: [identity, tools]\ntools: Tools",
+        )
+        .unwrap();
+        let user_yaml = serde_yaml::from_str(
+            "task_guidance:\n  append: Run the tests first.\nlayout:\n  prepend: team\n  append: [footer]\nextra:\n  append: New section.",
+        )
+        .unwrap();
+        let project_yaml =
+            serde_yaml::from_str("task_guidance:\n  prepend: Be brief.")
+                .unwrap();
+        let merged =
+            merge_yaml(&merge_yaml(&base_yaml, &user_yaml), &project_yaml);
+        assert_eq!(
+            merged["task_guidance"].as_str(),
+            Some("Be brief.\nFinish with finish_task.\nRun the tests first.")
+        );
+        let layout: Vec<&str> = merged["layout"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .filter_map(|v| v.as_str())
+            .collect();
+        assert_eq!(layout, vec!["team", "identity", "tools", "footer"]);
+        assert_eq!(merged["extra"].as_str(), Some("New section."));
+        assert_eq!(merged["tools"].as_str(), Some("Tools"));
+    }
 }